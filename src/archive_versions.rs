@@ -0,0 +1,438 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, error, info, trace};
+
+use crate::extensions::Extension;
+use crate::get_update::Update;
+use crate::notify::CheckedExtension;
+
+/// the identity of the archive's commits
+const GIT_NAME: &str = "extension-version-watcher";
+const GIT_EMAIL: &str = "extension-version-watcher@localhost";
+
+/// the index used to build trees, in the archive's git dir
+const INDEX_FILE: &str = "extension-version-watcher.index";
+
+fn default_path() -> String {
+    "./archive".into()
+}
+
+fn default_branch() -> String {
+    "main".into()
+}
+
+fn default_crx_dir() -> String {
+    "./crx".into()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// one branch with a directory for every extension
+    #[default]
+    Directory,
+    /// a branch for every extension, named after it
+    Branch,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveConfig {
+    /// the bare git repository, which is created if it doesn't exist
+    #[serde(default = "default_path")]
+    path: String,
+    #[serde(default)]
+    layout: Layout,
+    /// the branch that is committed to with the directory layout
+    #[serde(default = "default_branch")]
+    branch: String,
+    /// a remote that the updated branches and tags are pushed to after every run
+    remote: Option<String>,
+    /// where versions are extracted. this is always ./crx, except in tests
+    #[serde(skip, default = "default_crx_dir")]
+    crx_dir: String,
+}
+
+impl ArchiveConfig {
+    /// runs git in the archive with the index file, and returns its trimmed stdout
+    async fn git(&self, args: &[&str], stdin: Option<&[u8]>) -> Result<String> {
+        let stdout = self.git_bytes(args, stdin).await?;
+        Ok(String::from_utf8_lossy(&stdout).trim().to_string())
+    }
+
+    async fn git_bytes(&self, args: &[&str], stdin: Option<&[u8]>) -> Result<Vec<u8>> {
+        trace!(?args, "running git");
+        // git runs in the work tree when it's given one, so relative paths wouldn't work
+        let path = std::path::absolute(&self.path).context("couldn't resolve archive path")?;
+        let mut child = Command::new("git")
+            .arg("--git-dir")
+            .arg(&path)
+            .args(args)
+            .env("GIT_INDEX_FILE", path.join(INDEX_FILE))
+            .env("GIT_AUTHOR_NAME", GIT_NAME)
+            .env("GIT_AUTHOR_EMAIL", GIT_EMAIL)
+            .env("GIT_COMMITTER_NAME", GIT_NAME)
+            .env("GIT_COMMITTER_EMAIL", GIT_EMAIL)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("couldn't spawn git")?;
+        if let Some(stdin) = stdin {
+            let mut child_stdin = child.stdin.take().unwrap();
+            child_stdin
+                .write_all(stdin)
+                .await
+                .context("couldn't write to git's stdin")?;
+        }
+        let output = child
+            .wait_with_output()
+            .await
+            .context("couldn't wait for git")?;
+        if !output.status.success() {
+            bail!(
+                "git {} exited with {}: {}",
+                args[0],
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+
+    /// the commit a ref points to, if it exists
+    async fn resolve(&self, reference: &str) -> Option<String> {
+        self.git(&["rev-parse", "--verify", "--quiet", reference], None)
+            .await
+            .ok()
+    }
+
+    /// the tree with the files of a directory
+    async fn tree_of(&self, dir: &str) -> Result<String> {
+        self.git(&["read-tree", "--empty"], None).await?;
+        self.git(&["--work-tree", dir, "add", "--all", "--force", "."], None)
+            .await?;
+        self.git(&["write-tree"], None).await
+    }
+
+    /// the tree of the branch's latest commit, with the extension's directory replaced
+    async fn replace_directory(
+        &self,
+        parent: Option<&str>,
+        name: &str,
+        tree: &str,
+    ) -> Result<String> {
+        let mut entries = match parent {
+            Some(parent) => {
+                self.git(&["ls-tree", "-z", &format!("{parent}^{{tree}}")], None)
+                    .await?
+            }
+            None => String::new(),
+        }
+        .split('\0')
+        .filter(|entry| !entry.is_empty() && entry.split_once('\t').unwrap_or_default().1 != name)
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+        entries.push(format!("040000 tree {tree}\t{name}"));
+        let mut input = entries.join("\0");
+        input.push('\0');
+        self.git(&["mktree", "-z"], Some(input.as_bytes())).await
+    }
+
+    /// commits the extracted version and tags it, and returns the refs that were updated
+    async fn archive_version(&self, extension: &Extension, update: &Update) -> Result<Vec<String>> {
+        let dir = format!("{}/{}-{}", self.crx_dir, extension.name, update.cur_version);
+        let dir = tokio::fs::canonicalize(&dir)
+            .await
+            .with_context(|| format!("couldn't find {dir}"))?;
+        let tree = self
+            .tree_of(&dir.display().to_string())
+            .await
+            .context("couldn't add files")?;
+
+        let branch = match self.layout {
+            Layout::Directory => format!("refs/heads/{}", self.branch),
+            Layout::Branch => format!("refs/heads/{}", extension.name),
+        };
+        let parent = self.resolve(&branch).await;
+        let tree = match self.layout {
+            Layout::Directory => self
+                .replace_directory(parent.as_deref(), &extension.name, &tree)
+                .await
+                .context("couldn't replace directory")?,
+            Layout::Branch => tree,
+        };
+
+        let message = format!(
+            "{}: {} -> {}",
+            extension.name, update.prev_version, update.cur_version
+        );
+        let mut args = vec!["commit-tree", &tree, "-m", &message];
+        if let Some(ref parent) = parent {
+            args.extend(["-p", parent]);
+        }
+        let commit = self.git(&args, None).await.context("couldn't commit")?;
+        self.git(&["update-ref", &branch, &commit], None)
+            .await
+            .context("couldn't update branch")?;
+
+        // a version that is seen again, like after a downgrade, is tagged again
+        let tag = format!("refs/tags/{}-{}", extension.name, update.cur_version);
+        self.git(&["update-ref", &tag, &commit], None)
+            .await
+            .context("couldn't tag version")?;
+        debug!(commit, "archived version");
+        Ok(vec![branch, tag])
+    }
+}
+
+/// extracts an archived version to ./crx/{name}-{version}, for versions that were removed from ./crx
+pub async fn restore_version(config: &ArchiveConfig, name: &str, version: &str) -> Result<()> {
+    let tag = format!("refs/tags/{name}-{version}");
+    let tree = match config.layout {
+        Layout::Directory => format!("{tag}:{name}"),
+        Layout::Branch => tag,
+    };
+    let tar = config
+        .git_bytes(&["archive", "--format=tar", &tree], None)
+        .await
+        .with_context(|| format!("couldn't find {name} {version} in the archive"))?;
+
+    let dir = format!("{}/{name}-{version}", config.crx_dir);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .context("couldn't create dir for restored version")?;
+    let mut child = Command::new("tar")
+        .arg("-x")
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("couldn't spawn tar")?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(&tar)
+        .await
+        .context("couldn't write to tar's stdin")?;
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .await
+        .context("couldn't wait for tar")?;
+    if !output.status.success() {
+        bail!(
+            "couldn't extract version: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    info!(name, version, "restored version from the archive");
+    Ok(())
+}
+
+/// commits every new version to the archive, and pushes them to the remote
+#[tracing::instrument(skip_all)]
+pub async fn archive_versions(
+    config: &ArchiveConfig,
+    checked_extensions: &[CheckedExtension],
+) -> Result<()> {
+    if !tokio::fs::try_exists(&config.path)
+        .await
+        .context("couldn't check if the archive exists")?
+    {
+        info!(path = config.path, "creating archive");
+        let output = Command::new("git")
+            .args(["init", "--quiet", "--bare", &config.path])
+            .output()
+            .await
+            .context("couldn't spawn git")?;
+        if !output.status.success() {
+            bail!(
+                "couldn't create archive: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        // so that clones check out the branch
+        if config.layout == Layout::Directory {
+            let head = format!("refs/heads/{}", config.branch);
+            config
+                .git(&["symbolic-ref", "HEAD", &head], None)
+                .await
+                .context("couldn't set HEAD")?;
+        }
+    }
+
+    let mut updated_refs = vec![];
+    for (extension, update) in checked_extensions {
+        let Ok(Some(update)) = update else {
+            continue;
+        };
+        match config.archive_version(extension, update).await {
+            Ok(refs) => {
+                info!(extension = extension.name, "archived version");
+                for reference in refs {
+                    if !updated_refs.contains(&reference) {
+                        updated_refs.push(reference);
+                    }
+                }
+            }
+            Err(error) => error!(
+                extension = extension.name,
+                "failed to archive version: {error:#}"
+            ),
+        }
+    }
+    if let Err(error) = tokio::fs::remove_file(format!("{}/{INDEX_FILE}", config.path)).await {
+        debug!(%error, "failed to remove index file");
+    }
+
+    if let Some(ref remote) = config.remote {
+        if !updated_refs.is_empty() {
+            let refspecs = updated_refs
+                .iter()
+                .map(|reference| format!("+{reference}:{reference}"))
+                .collect::<Vec<_>>();
+            let mut args = vec!["push", "--quiet", remote.as_str()];
+            args.extend(refspecs.iter().map(String::as_str));
+            config
+                .git(&args, None)
+                .await
+                .context("couldn't push archive")?;
+            info!(refs = updated_refs.len(), "pushed archive");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::notify::updated_extension;
+
+    fn checked(version: &str, prev_version: &str) -> CheckedExtension {
+        let (extension, update) = updated_extension("foo", None);
+        let mut update = update.unwrap().unwrap();
+        update.prev_version = prev_version.into();
+        update.cur_version = version.into();
+        (extension, Ok(Some(update)))
+    }
+
+    fn write_version(crx_dir: &Path, version: &str, contents: &str) {
+        let dir = crx_dir.join(format!("foo-{version}")).display().to_string();
+        std::fs::create_dir_all(format!("{dir}/js")).unwrap();
+        std::fs::write(format!("{dir}/manifest.json"), version).unwrap();
+        std::fs::write(format!("{dir}/js/background.js"), contents).unwrap();
+    }
+
+    fn remote_git(remote: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(remote)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn archives_pushes_and_restores_versions() {
+        let root = std::env::temp_dir().join(format!(
+            "extension-version-watcher-archive-{}",
+            std::process::id()
+        ));
+        let crx_dir = root.join("crx");
+        std::fs::create_dir_all(&root).unwrap();
+
+        for (layout, branch) in [
+            (Layout::Directory, "refs/heads/main"),
+            (Layout::Branch, "refs/heads/foo"),
+        ] {
+            let _ = std::fs::remove_dir_all(&crx_dir);
+            let remote = root.join(format!("{layout:?}-remote.git"));
+            let output = std::process::Command::new("git")
+                .args(["init", "--quiet", "--bare"])
+                .arg(&remote)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            let config = ArchiveConfig {
+                path: root
+                    .join(format!("{layout:?}-archive.git"))
+                    .display()
+                    .to_string(),
+                layout,
+                branch: "main".into(),
+                remote: Some(remote.display().to_string()),
+                crx_dir: crx_dir.display().to_string(),
+            };
+
+            write_version(&crx_dir, "1.0", "console.log(1)");
+            archive_versions(&config, &[checked("1.0", "None")])
+                .await
+                .unwrap();
+            write_version(&crx_dir, "1.1", "console.log(2)");
+            archive_versions(&config, &[checked("1.1", "1.0")])
+                .await
+                .unwrap();
+
+            // both versions are on the branch and tagged in the remote
+            assert_eq!(remote_git(&remote, &["rev-list", "--count", branch]), "2");
+            assert_eq!(
+                remote_git(&remote, &["log", "-1", "--format=%s", branch]),
+                "foo: 1.0 -> 1.1"
+            );
+            assert_eq!(
+                remote_git(&remote, &["rev-parse", branch]),
+                remote_git(&remote, &["rev-parse", "refs/tags/foo-1.1"])
+            );
+            assert_eq!(
+                remote_git(&remote, &["rev-parse", &format!("{branch}~1")]),
+                remote_git(&remote, &["rev-parse", "refs/tags/foo-1.0"])
+            );
+
+            std::fs::remove_dir_all(&crx_dir).unwrap();
+            for (version, contents) in [("1.0", "console.log(1)"), ("1.1", "console.log(2)")] {
+                restore_version(&config, "foo", version).await.unwrap();
+                let dir = crx_dir.join(format!("foo-{version}")).display().to_string();
+                assert_eq!(
+                    std::fs::read_to_string(format!("{dir}/manifest.json")).unwrap(),
+                    version
+                );
+                assert_eq!(
+                    std::fs::read_to_string(format!("{dir}/js/background.js")).unwrap(),
+                    contents
+                );
+            }
+            assert!(restore_version(&config, "foo", "2.0").await.is_err());
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
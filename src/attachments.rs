@@ -0,0 +1,385 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+const fn default_max_attachment_size() -> usize {
+    10 * 1024 * 1024
+}
+
+/// what is done with attachments that are larger than max_attachment_size
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Oversized {
+    /// compress the file with gzip
+    #[default]
+    Gzip,
+    /// compress the file into a zip archive
+    Zip,
+    /// split diffs into one file per top level directory, compressing parts that are still too large
+    Split,
+    /// don't attach the file, only link to it
+    Link,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttachmentsConfig {
+    /// in bytes
+    #[serde(default = "default_max_attachment_size")]
+    max_attachment_size: usize,
+    #[serde(default)]
+    oversized_attachments: Oversized,
+    /// files that can't be attached are linked to as `{attachment_base_url}/{filename}`. if this isn't set, the path
+    /// of the file in ./diff is mentioned instead
+    attachment_base_url: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Attachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// the mime type of the attachment, based on its extension
+    pub fn content_type(&self) -> &'static str {
+        match self.filename.rsplit('.').next() {
+            Some("diff") => "text/x-diff",
+            Some("html") => "text/html",
+            Some("gz") => "application/gzip",
+            Some("zip") => "application/zip",
+            _ => "application/octet-stream",
+        }
+    }
+}
+
+fn format_size(size: usize) -> String {
+    if size >= 1024 * 1024 {
+        format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KiB", size as f64 / 1024.0)
+    }
+}
+
+fn gzip(attachment: &Attachment) -> Result<Attachment> {
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+    encoder.write_all(&attachment.data)?;
+    Ok(Attachment {
+        filename: format!("{}.gz", attachment.filename),
+        data: encoder.finish()?,
+    })
+}
+
+fn zip(attachment: &Attachment) -> Result<Attachment> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    writer.start_file(
+        attachment.filename.as_str(),
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated),
+    )?;
+    writer.write_all(&attachment.data)?;
+    Ok(Attachment {
+        filename: format!("{}.zip", attachment.filename),
+        data: writer.finish()?.into_inner(),
+    })
+}
+
+/// splits a diff into one diff per top level directory of the extension, like `js` or `unpacked/sources`
+fn split_diff(attachment: &Attachment) -> Vec<Attachment> {
+    let diff = String::from_utf8_lossy(&attachment.data);
+    let mut parts: BTreeMap<String, String> = BTreeMap::new();
+    let mut current = String::from("root");
+    for line in diff.split_inclusive('\n') {
+        let path = if let Some(paths) = line.strip_prefix("diff -U 10 -r ") {
+            paths.trim_end().rsplit(' ').next()
+        } else if let Some(only) = line.strip_prefix("Only in ") {
+            only.trim_end().split_once(": ").map(|(dir, _)| dir)
+        } else if let Some(paths) = line.strip_prefix("Binary files ") {
+            paths.trim_end().rsplit(" and ").next()
+        } else {
+            None
+        };
+        if let Some(path) = path {
+            // ./{name}-{version}[-unpacked]/{top level directory}/...
+            let mut components = path.trim_start_matches("./").split('/');
+            let tree = components.next().unwrap_or_default();
+            let rest = components.collect::<Vec<_>>();
+            // "Only in" lines name a directory, everything else names a file
+            let in_directory = rest.len() > 1 || (line.starts_with("Only in ") && !rest.is_empty());
+            let mut group = if in_directory { rest[0] } else { "root" }.to_string();
+            for suffix in ["unpacked", "normalized"] {
+                if tree.ends_with(&format!("-{suffix}")) {
+                    group = format!("{suffix}-{group}");
+                }
+            }
+            current = group
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+        }
+        parts.entry(current.clone()).or_default().push_str(line);
+    }
+
+    let stem = attachment
+        .filename
+        .strip_suffix(".diff")
+        .unwrap_or(&attachment.filename);
+    parts
+        .into_iter()
+        .map(|(group, diff)| Attachment {
+            filename: format!("{stem}.{group}.diff"),
+            data: diff.into_bytes(),
+        })
+        .collect()
+}
+
+impl AttachmentsConfig {
+    fn location(&self, filename: &str) -> String {
+        match self.attachment_base_url {
+            Some(ref base_url) => format!("<{}/{filename}>", base_url.trim_end_matches('/')),
+            None => format!("`./diff/{filename}`"),
+        }
+    }
+
+    fn link(&self, filename: &str, size: usize) -> String {
+        format!(
+            "`{filename}` is too large to attach ({}), see {}",
+            format_size(size),
+            self.location(filename)
+        )
+    }
+
+    /// notes that link to every attachment, for notifiers that can't send files
+    pub fn links(&self, attachments: &[Attachment]) -> Vec<String> {
+        attachments
+            .iter()
+            .map(|attachment| {
+                format!(
+                    "`{}` ({}): {}",
+                    attachment.filename,
+                    format_size(attachment.data.len()),
+                    self.location(&attachment.filename)
+                )
+            })
+            .collect()
+    }
+
+    fn compress(&self, attachment: Attachment, zip_file: bool) -> Result<Attachment> {
+        let compressed = if zip_file {
+            zip(&attachment).context("couldn't zip attachment")?
+        } else {
+            gzip(&attachment).context("couldn't gzip attachment")?
+        };
+        debug!(
+            filename = attachment.filename,
+            size = attachment.data.len(),
+            compressed_size = compressed.data.len(),
+            "compressed attachment"
+        );
+        Ok(compressed)
+    }
+
+    /// makes every attachment fit in max_attachment_size. returns the attachments that can be sent and notes about
+    /// attachments that were changed or left out, which should be added to the message
+    pub fn prepare(&self, attachments: Vec<Attachment>) -> (Vec<Attachment>, Vec<String>) {
+        let mut prepared = vec![];
+        let mut notes = vec![];
+        for attachment in attachments {
+            let size = attachment.data.len();
+            if size <= self.max_attachment_size {
+                prepared.push(attachment);
+                continue;
+            }
+
+            let filename = attachment.filename.clone();
+            let candidates = match self.oversized_attachments {
+                Oversized::Link => vec![],
+                Oversized::Split if filename.ends_with(".diff") => split_diff(&attachment),
+                _ => vec![attachment],
+            };
+            let mut fitted = vec![];
+            for candidate in candidates {
+                if candidate.data.len() <= self.max_attachment_size {
+                    fitted.push(candidate);
+                    continue;
+                }
+                match self.compress(candidate, self.oversized_attachments == Oversized::Zip) {
+                    Ok(compressed) if compressed.data.len() <= self.max_attachment_size => {
+                        fitted.push(compressed)
+                    }
+                    Ok(_) => {
+                        fitted.clear();
+                        break;
+                    }
+                    Err(error) => {
+                        warn!(filename, "{error:#}");
+                        fitted.clear();
+                        break;
+                    }
+                }
+            }
+
+            if fitted.is_empty() {
+                notes.push(self.link(&filename, size));
+            } else {
+                notes.push(format!(
+                    "`{filename}` ({}) was attached as {}",
+                    format_size(size),
+                    fitted
+                        .iter()
+                        .map(|a| format!("`{}`", a.filename))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+                prepared.extend(fitted);
+            }
+        }
+        (prepared, notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn config(oversized: &str) -> AttachmentsConfig {
+        toml::from_str(&format!(
+            r#"
+            max_attachment_size = 1000
+            oversized_attachments = "{oversized}"
+            attachment_base_url = "https://example.com/diff/"
+            "#
+        ))
+        .unwrap()
+    }
+
+    fn attachment(filename: &str, data: Vec<u8>) -> Attachment {
+        Attachment {
+            filename: filename.into(),
+            data,
+        }
+    }
+
+    /// bytes that don't compress
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_attachments_are_unchanged() {
+        let (prepared, notes) =
+            config("gzip").prepare(vec![attachment("a.diff", vec![b'a'; 1000])]);
+        assert_eq!(prepared.len(), 1);
+        assert_eq!(prepared[0].filename, "a.diff");
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn gzip_and_zip() {
+        let diff = "+ line\n".repeat(500).into_bytes();
+
+        let (prepared, notes) = config("gzip").prepare(vec![attachment("a.diff", diff.clone())]);
+        assert_eq!(prepared.len(), 1);
+        assert_eq!(prepared[0].filename, "a.diff.gz");
+        assert!(prepared[0].data.len() <= 1000);
+        let mut decompressed = vec![];
+        flate2::read::GzDecoder::new(&prepared[0].data[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, diff);
+        assert_eq!(notes, ["`a.diff` (3.4 KiB) was attached as `a.diff.gz`"]);
+
+        let (prepared, notes) = config("zip").prepare(vec![attachment("a.diff", diff.clone())]);
+        assert_eq!(prepared[0].filename, "a.diff.zip");
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(&prepared[0].data)).unwrap();
+        let mut decompressed = vec![];
+        archive
+            .by_name("a.diff")
+            .unwrap()
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, diff);
+        assert_eq!(notes, ["`a.diff` (3.4 KiB) was attached as `a.diff.zip`"]);
+    }
+
+    #[test]
+    fn link_when_too_large() {
+        let too_large =
+            "`a.html` is too large to attach (3.9 KiB), see <https://example.com/diff/a.html>";
+
+        // links are used right away with oversized_attachments = "link"
+        let (prepared, notes) = config("link").prepare(vec![
+            attachment("a.html", vec![b'a'; 4000]),
+            attachment("b.diff", vec![b'b'; 10]),
+        ]);
+        assert_eq!(prepared.len(), 1);
+        assert_eq!(prepared[0].filename, "b.diff");
+        assert_eq!(notes, [too_large]);
+
+        // and when compression doesn't help
+        let (prepared, notes) = config("gzip").prepare(vec![attachment("a.html", noise(4000))]);
+        assert!(prepared.is_empty());
+        assert_eq!(notes, [too_large]);
+    }
+
+    #[test]
+    fn split_diffs() {
+        let js = format!(
+            "diff -U 10 -r ./a-1.0/js/a.js ./a-1.1/js/a.js\n{}",
+            "+x\n".repeat(200)
+        );
+        let css = format!(
+            "diff -U 10 -r ./a-1.0/css/a.css ./a-1.1/css/a.css\n{}",
+            "+y\n".repeat(200)
+        );
+        let diff = format!("{js}{css}");
+        let (prepared, notes) =
+            config("split").prepare(vec![attachment("a-1.0-1.1.diff", diff.into_bytes())]);
+        let filenames = prepared
+            .iter()
+            .map(|a| a.filename.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(filenames, ["a-1.0-1.1.css.diff", "a-1.0-1.1.js.diff"]);
+        assert_eq!(prepared[0].data, css.as_bytes());
+        assert_eq!(prepared[1].data, js.as_bytes());
+        assert_eq!(
+            notes,
+            ["`a-1.0-1.1.diff` (1.3 KiB) was attached as `a-1.0-1.1.css.diff`, `a-1.0-1.1.js.diff`"]
+        );
+    }
+}
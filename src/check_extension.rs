@@ -43,40 +43,38 @@ pub async fn check_extension(extension: &Extension) -> Result<(String, String)>
         let mut crx_url = None;
 
         for e in parser {
-            match e.context("couldn't visit XML")? {
-                XmlEvent::StartElement {
-                    name,
-                    attributes,
-                    namespace: _,
-                } => {
-                    if found_app {
-                        if name.local_name == "updatecheck" {
-                            trace!("found updatecheck element");
+            if let XmlEvent::StartElement {
+                name,
+                attributes,
+                namespace: _,
+            } = e.context("couldn't visit XML")?
+            {
+                if found_app {
+                    if name.local_name == "updatecheck" {
+                        trace!("found updatecheck element");
 
-                            if let Some(codebase) =
-                                attributes.iter().find(|a| a.name.local_name == *"codebase")
-                            {
-                                trace!("found codebase attribute");
-                                crx_url = Some(codebase.value.clone());
-                            }
+                        if let Some(codebase) =
+                            attributes.iter().find(|a| a.name.local_name == *"codebase")
+                        {
+                            trace!("found codebase attribute");
+                            crx_url = Some(codebase.value.clone());
+                        }
 
-                            if let Some(version) =
-                                attributes.iter().find(|a| a.name.local_name == *"version")
-                            {
-                                trace!("found version attribute");
-                                cur_version = Some(version.value.clone());
-                            }
+                        if let Some(version) =
+                            attributes.iter().find(|a| a.name.local_name == *"version")
+                        {
+                            trace!("found version attribute");
+                            cur_version = Some(version.value.clone());
                         }
-                    } else if name.local_name == "app"
-                        && attributes
-                            .iter()
-                            .any(|a| a.name.local_name == *"appid" && a.value == *extension.id)
-                    {
-                        found_app = true;
-                        trace!("found app element");
                     }
+                } else if name.local_name == "app"
+                    && attributes
+                        .iter()
+                        .any(|a| a.name.local_name == *"appid" && a.value == *extension.id)
+                {
+                    found_app = true;
+                    trace!("found app element");
                 }
-                _ => {}
             }
         }
 
@@ -0,0 +1,119 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use crate::get_update::Update;
+
+/// which component of the version changed. chrome extension versions are 1 to 4 dot separated integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionChange {
+    Major,
+    Minor,
+    Patch,
+    Build,
+    Downgrade,
+    /// the versions couldn't be parsed or are equal
+    Other,
+}
+
+impl Display for VersionChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            VersionChange::Major => "major",
+            VersionChange::Minor => "minor",
+            VersionChange::Patch => "patch",
+            VersionChange::Build => "build",
+            VersionChange::Downgrade => "downgrade",
+            VersionChange::Other => "other",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// nothing that needs a closer look
+    Low,
+    /// manifest or endpoint changes, a major version or a downgrade
+    Medium,
+    /// new permissions or possible secrets
+    High,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        })
+    }
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version.split('.').map(|c| c.parse().ok()).collect()
+}
+
+pub fn version_change(prev: &str, cur: &str) -> VersionChange {
+    let (Some(mut prev), Some(mut cur)) = (parse_version(prev), parse_version(cur)) else {
+        return VersionChange::Other;
+    };
+    // missing components are 0, so 1.2 -> 1.2.1 is a patch
+    let len = prev.len().max(cur.len()).max(4);
+    prev.resize(len, 0);
+    cur.resize(len, 0);
+    match cur.cmp(&prev) {
+        Ordering::Less => VersionChange::Downgrade,
+        Ordering::Equal => VersionChange::Other,
+        Ordering::Greater => match prev.iter().zip(&cur).position(|(p, c)| p != c) {
+            Some(0) => VersionChange::Major,
+            Some(1) => VersionChange::Minor,
+            Some(2) => VersionChange::Patch,
+            _ => VersionChange::Build,
+        },
+    }
+}
+
+impl Update {
+    pub fn version_change(&self) -> VersionChange {
+        version_change(&self.prev_version, &self.cur_version)
+    }
+
+    pub fn severity(&self) -> Severity {
+        let manifest_changes = self.manifest_changes.as_ref();
+        if !self.secrets.is_empty()
+            || manifest_changes.is_some_and(|c| !c.added_permissions.is_empty())
+        {
+            Severity::High
+        } else if manifest_changes.is_some_and(|c| !c.is_empty())
+            || self
+                .endpoint_changes
+                .as_ref()
+                .is_some_and(|c| !c.is_empty())
+            || matches!(
+                self.version_change(),
+                VersionChange::Major | VersionChange::Downgrade
+            )
+        {
+            Severity::Medium
+        } else {
+            Severity::Low
+        }
+    }
+}
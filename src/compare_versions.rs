@@ -0,0 +1,99 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::{bail, Context, Result};
+
+use crate::archive_versions::restore_version;
+use crate::extensions::Extension;
+use crate::file_changes::{diff_changes, file_changes, ignore_set};
+use crate::find_endpoints::load_endpoints;
+use crate::get_update::Update;
+use crate::manifest::load_manifest;
+use crate::Config;
+
+/// makes sure the version is extracted in ./crx, restoring it from the archive if it was removed
+pub async fn find_version(config: &Config, name: &str, version: &str) -> Result<()> {
+    // versions are used in paths
+    if version.is_empty()
+        || version.starts_with('.')
+        || !version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        bail!("invalid version {version:?}");
+    }
+    if tokio::fs::try_exists(format!("./crx/{name}-{version}"))
+        .await
+        .context("couldn't check if version exists")?
+    {
+        return Ok(());
+    }
+    match config.archive {
+        Some(ref archive) => restore_version(archive, name, version).await,
+        None => bail!("{name} {version} isn't in ./crx"),
+    }
+}
+
+/// compares any two versions of an extension like an update from one to the other, with a diff. the versions can be
+/// in either order
+#[tracing::instrument(skip(config))]
+pub async fn compare_versions(
+    config: &Config,
+    extension: &Extension,
+    prev_version: &str,
+    cur_version: &str,
+) -> Result<Update> {
+    find_version(config, &extension.name, prev_version).await?;
+    find_version(config, &extension.name, cur_version).await?;
+
+    let prev_dir = format!("{}-{prev_version}", extension.name);
+    let cur_dir = format!("{}-{cur_version}", extension.name);
+    let ignore = ignore_set(config, extension)?;
+    let file_changes = file_changes(&prev_dir, &cur_dir, &ignore)
+        .await
+        .context("couldn't compare files")?;
+    let diff = diff_changes(&prev_dir, &cur_dir, &file_changes)
+        .await
+        .context("couldn't get diff")?;
+    let manifest_changes = match (
+        load_manifest(&extension.name, prev_version).await,
+        load_manifest(&extension.name, cur_version).await,
+    ) {
+        (Ok(prev_manifest), Ok(cur_manifest)) => Some(cur_manifest.changes(&prev_manifest)),
+        _ => None,
+    };
+    let endpoint_changes = match (
+        load_endpoints(&extension.name, prev_version).await,
+        load_endpoints(&extension.name, cur_version).await,
+    ) {
+        (Some(prev_endpoints), Some(cur_endpoints)) => Some(cur_endpoints.changes(&prev_endpoints)),
+        _ => None,
+    };
+    Ok(Update {
+        prev_version: prev_version.into(),
+        cur_version: cur_version.into(),
+        diff: Some(diff),
+        normalized_diff: None,
+        html_report: None,
+        file_changes,
+        manifest_changes,
+        endpoint_changes,
+        secrets: vec![],
+        format_failures: vec![],
+    })
+}
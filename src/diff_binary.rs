@@ -0,0 +1,215 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use tracing::{debug, trace};
+use wasmparser::{ExternalKind, Parser, Payload, TypeRef};
+
+/// wasm files larger than this aren't disassembled, only their imports and exports are compared
+const MAX_DISASSEMBLED_SIZE: usize = 4 * 1024 * 1024;
+
+/// how many bytes at the start of a file are checked for NUL bytes to detect binary files
+const BINARY_CHECK_LEN: usize = 32 * 1024;
+
+/// used to give temporary wat files unique names
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// describes how a binary file changed, since diff can only say that it did. paths are relative to ./crx
+#[tracing::instrument]
+pub async fn diff_binary(prev_path: &str, cur_path: &str) -> Result<String> {
+    let prev = tokio::fs::read(format!("./crx/{prev_path}"))
+        .await
+        .with_context(|| format!("couldn't read {prev_path}"))?;
+    let cur = tokio::fs::read(format!("./crx/{cur_path}"))
+        .await
+        .with_context(|| format!("couldn't read {cur_path}"))?;
+
+    let mut output = format!("Binary files {prev_path} and {cur_path} differ\n");
+    output.push_str(&format!("  size: {} -> {} bytes\n", prev.len(), cur.len()));
+    output.push_str(&format!(
+        "  sha256: {:x} -> {:x}\n",
+        Sha256::digest(&prev),
+        Sha256::digest(&cur)
+    ));
+    if let (Ok(prev_size), Ok(cur_size)) = (imagesize::blob_size(&prev), imagesize::blob_size(&cur))
+    {
+        output.push_str(&format!(
+            "  dimensions: {}x{} -> {}x{}\n",
+            prev_size.width, prev_size.height, cur_size.width, cur_size.height
+        ));
+    }
+    if prev.starts_with(b"\0asm") && cur.starts_with(b"\0asm") {
+        output.push_str(&diff_wasm(prev_path, cur_path, prev, cur).await?);
+    }
+    Ok(output)
+}
+
+/// describes a file that was added or removed if it is binary, since diff only says that it exists. the path is
+/// relative to ./crx. the imports and exports of wasm modules are listed like in diff_binary, as added or removed
+#[tracing::instrument]
+pub async fn describe_binary(path: &str, added: bool) -> Result<Option<String>> {
+    let contents = tokio::fs::read(format!("./crx/{path}"))
+        .await
+        .with_context(|| format!("couldn't read {path}"))?;
+    // the same check that diff uses
+    if !contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0) {
+        return Ok(None);
+    }
+    let mut output = format!("  size: {} bytes\n", contents.len());
+    output.push_str(&format!("  sha256: {:x}\n", Sha256::digest(&contents)));
+    if let Ok(size) = imagesize::blob_size(&contents) {
+        output.push_str(&format!("  dimensions: {}x{}\n", size.width, size.height));
+    }
+    if contents.starts_with(b"\0asm") {
+        match wasm_imports_exports(&contents) {
+            Ok(items) => {
+                for item in items {
+                    output.push_str(&format!("  {} {item}\n", if added { '+' } else { '-' }));
+                }
+            }
+            Err(error) => debug!("couldn't parse wasm: {error:#}"),
+        }
+    }
+    Ok(Some(output))
+}
+
+async fn diff_wasm(prev_path: &str, cur_path: &str, prev: Vec<u8>, cur: Vec<u8>) -> Result<String> {
+    trace!("disassembling wasm");
+    let (disassembled, prev, cur) = tokio::task::spawn_blocking(move || {
+        let disassembled = if prev.len().max(cur.len()) <= MAX_DISASSEMBLED_SIZE {
+            wasmprinter::print_bytes(&prev)
+                .and_then(|prev_wat| Ok((prev_wat, wasmprinter::print_bytes(&cur)?)))
+        } else {
+            Err(anyhow!("too large to disassemble"))
+        };
+        (disassembled, prev, cur)
+    })
+    .await
+    .unwrap();
+
+    match disassembled {
+        Ok((prev_wat, cur_wat)) => {
+            diff_text(
+                &format!("{prev_path}.wat"),
+                &format!("{cur_path}.wat"),
+                prev_wat,
+                cur_wat,
+            )
+            .await
+        }
+        Err(error) => {
+            debug!("couldn't disassemble wasm, comparing imports and exports instead: {error:#}");
+            // the size and hash are already in the output, which is all that's left to say about invalid wasm
+            let (prev_items, cur_items) =
+                match (wasm_imports_exports(&prev), wasm_imports_exports(&cur)) {
+                    (Ok(prev_items), Ok(cur_items)) => (prev_items, cur_items),
+                    (Err(error), _) | (_, Err(error)) => {
+                        debug!("couldn't parse wasm: {error:#}");
+                        return Ok(String::new());
+                    }
+                };
+            let mut output = String::new();
+            for removed in prev_items.difference(&cur_items) {
+                output.push_str(&format!("  - {removed}\n"));
+            }
+            for added in cur_items.difference(&prev_items) {
+                output.push_str(&format!("  + {added}\n"));
+            }
+            Ok(output)
+        }
+    }
+}
+
+/// lists a wasm module's imports and exports, like `import env.memory (memory)` and `export main (func)`
+fn wasm_imports_exports(wasm: &[u8]) -> Result<BTreeSet<String>> {
+    let mut items = BTreeSet::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import?;
+                    let kind = match import.ty {
+                        TypeRef::Func(_) | TypeRef::FuncExact(_) => "func",
+                        TypeRef::Table(_) => "table",
+                        TypeRef::Memory(_) => "memory",
+                        TypeRef::Global(_) => "global",
+                        TypeRef::Tag(_) => "tag",
+                    };
+                    items.insert(format!("import {}.{} ({kind})", import.module, import.name));
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    let kind = match export.kind {
+                        ExternalKind::Func | ExternalKind::FuncExact => "func",
+                        ExternalKind::Table => "table",
+                        ExternalKind::Memory => "memory",
+                        ExternalKind::Global => "global",
+                        ExternalKind::Tag => "tag",
+                    };
+                    items.insert(format!("export {} ({kind})", export.name));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+/// diffs two strings by writing them to temporary files
+async fn diff_text(prev_label: &str, cur_label: &str, prev: String, cur: String) -> Result<String> {
+    let tmp_path = |side: &str| {
+        std::env::temp_dir().join(format!(
+            "extension-version-watcher-{}-{}-{side}.wat",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))
+    };
+    let (prev_tmp, cur_tmp) = (tmp_path("prev"), tmp_path("cur"));
+    tokio::fs::write(&prev_tmp, prev)
+        .await
+        .context("couldn't write temporary file")?;
+    tokio::fs::write(&cur_tmp, cur)
+        .await
+        .context("couldn't write temporary file")?;
+    let diff = Command::new("diff")
+        .arg("-U")
+        .arg("10")
+        .arg("--label")
+        .arg(prev_label)
+        .arg("--label")
+        .arg(cur_label)
+        .arg(&prev_tmp)
+        .arg(&cur_tmp)
+        .output()
+        .await;
+    for tmp in [&prev_tmp, &cur_tmp] {
+        let _ = tokio::fs::remove_file(tmp).await;
+    }
+    let diff = diff.context("couldn't get diff")?;
+    Ok(format!(
+        "diff -U 10 -r {prev_label} {cur_label}\n{}",
+        String::from_utf8_lossy(&diff.stdout)
+    ))
+}
@@ -0,0 +1,266 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use twilight_model::channel::message::embed::{Embed, EmbedField};
+
+use crate::classify_update::Severity;
+use crate::extensions::Extension;
+use crate::file_changes::ChangeKind;
+use crate::get_update::Update;
+
+// discord's limits on embeds, in characters
+const TITLE_LEN: usize = 256;
+const DESCRIPTION_LEN: usize = 4096;
+const FIELD_VALUE_LEN: usize = 1024;
+/// the limit on the combined length of every embed in a message
+pub const MAX_TOTAL_LEN: usize = 6000;
+/// the limit on the number of embeds in a message
+pub const MAX_EMBEDS: usize = 10;
+
+const LOW_COLOR: u32 = 0x57f287;
+const MEDIUM_COLOR: u32 = 0xfee75c;
+const HIGH_COLOR: u32 = 0xe67e22;
+const ERROR_COLOR: u32 = 0xed4245;
+
+fn truncate(text: &str, max_len: usize) -> String {
+    if text.chars().count() > max_len {
+        format!("{}…", text.chars().take(max_len - 1).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+/// lists items one per line, leaving out the ones that don't fit in a field
+fn list_field(name: &str, items: impl ExactSizeIterator<Item = String>) -> EmbedField {
+    let total = items.len();
+    let mut value = String::new();
+    for (i, item) in items.enumerate() {
+        let line = format!("{}\n", truncate(&item, 200));
+        // leave room for the "and N more" line
+        if value.len() + line.len() > FIELD_VALUE_LEN - 20 {
+            value.push_str(&format!("and {} more", total - i));
+            break;
+        }
+        value.push_str(&line);
+    }
+    field(name, value.trim_end(), false)
+}
+
+fn field(name: &str, value: &str, inline: bool) -> EmbedField {
+    EmbedField {
+        inline,
+        name: name.into(),
+        value: truncate(value, FIELD_VALUE_LEN),
+    }
+}
+
+fn embed(title: &str, color: u32) -> Embed {
+    Embed {
+        author: None,
+        color: Some(color),
+        description: None,
+        fields: vec![],
+        footer: None,
+        image: None,
+        kind: "rich".into(),
+        provider: None,
+        thumbnail: None,
+        timestamp: None,
+        title: Some(truncate(title, TITLE_LEN)),
+        url: None,
+        video: None,
+    }
+}
+
+/// the number of characters discord counts towards MAX_TOTAL_LEN
+pub fn embed_len(embed: &Embed) -> usize {
+    let len = |text: &Option<String>| text.as_ref().map_or(0, |t| t.chars().count());
+    len(&embed.title)
+        + len(&embed.description)
+        + embed
+            .fields
+            .iter()
+            .map(|f| f.name.chars().count() + f.value.chars().count())
+            .sum::<usize>()
+}
+
+pub fn update_embed(extension: &Extension, update: &Update) -> Embed {
+    let severity = update.severity();
+    let mut embed = embed(
+        &extension.display_name,
+        match severity {
+            Severity::Low => LOW_COLOR,
+            Severity::Medium => MEDIUM_COLOR,
+            Severity::High => HIGH_COLOR,
+        },
+    );
+    // extensions with an update url usually aren't on the webstore
+    if extension.url.is_none() {
+        embed.url = Some(format!(
+            "https://chromewebstore.google.com/detail/{}",
+            extension.id
+        ));
+    }
+
+    embed.fields.push(field(
+        "Previous Version",
+        &format!("`{}`", update.prev_version),
+        true,
+    ));
+    embed.fields.push(field(
+        "Current Version",
+        &format!("`{}`", update.cur_version),
+        true,
+    ));
+    embed.fields.push(field(
+        "Change",
+        &format!(
+            "{} (severity: {severity}){}",
+            update.version_change(),
+            if update.diff.is_some() {
+                "\ndiff automatically generated"
+            } else {
+                ""
+            }
+        ),
+        true,
+    ));
+
+    if !update.file_changes.is_empty() {
+        let count = |kind| {
+            update
+                .file_changes
+                .iter()
+                .filter(|c| c.kind == kind)
+                .count()
+        };
+        let ignored = update.file_changes.iter().filter(|c| c.ignored).count();
+        embed.fields.push(field(
+            "Files",
+            &format!(
+                "{} added, {} removed, {} modified{}",
+                count(ChangeKind::Added),
+                count(ChangeKind::Removed),
+                count(ChangeKind::Modified),
+                if ignored > 0 {
+                    format!(" ({ignored} ignored)")
+                } else {
+                    "".into()
+                }
+            ),
+            false,
+        ));
+    }
+    if let Some(ref manifest_changes) = update.manifest_changes {
+        if !manifest_changes.added_permissions.is_empty() {
+            embed.fields.push(list_field(
+                "Added Permissions",
+                manifest_changes
+                    .added_permissions
+                    .iter()
+                    .map(|p| format!("`{p}`")),
+            ));
+        }
+        if !manifest_changes.removed_permissions.is_empty() {
+            embed.fields.push(list_field(
+                "Removed Permissions",
+                manifest_changes
+                    .removed_permissions
+                    .iter()
+                    .map(|p| format!("`{p}`")),
+            ));
+        }
+        if !manifest_changes.changed_keys.is_empty() {
+            embed.fields.push(list_field(
+                "Changed Manifest Keys",
+                manifest_changes
+                    .changed_keys
+                    .iter()
+                    .map(|(key, _, _)| format!("`{key}`")),
+            ));
+        }
+    }
+    if let Some(ref endpoint_changes) = update.endpoint_changes {
+        if !endpoint_changes.is_empty() {
+            let endpoints = endpoint_changes
+                .added
+                .labeled()
+                .map(|(kind, endpoint)| format!("added {kind} `{endpoint}`"))
+                .chain(
+                    endpoint_changes
+                        .removed
+                        .labeled()
+                        .map(|(kind, endpoint)| format!("removed {kind} `{endpoint}`")),
+                )
+                .collect::<Vec<_>>();
+            embed
+                .fields
+                .push(list_field("Endpoint Changes", endpoints.into_iter()));
+        }
+    }
+    if !update.secrets.is_empty() {
+        embed.fields.push(list_field(
+            "New Possible Secrets",
+            update.secrets.iter().map(|secret| {
+                format!(
+                    "{} `{}` in `{}:{}`",
+                    secret.kind,
+                    secret.displayed_value(),
+                    secret.file,
+                    secret.line
+                )
+            }),
+        ));
+    }
+    if !update.format_failures.is_empty() {
+        embed.fields.push(list_field(
+            "Formatter Failures",
+            update
+                .format_failures
+                .iter()
+                .map(|failure| format!("`{}` ({})", failure.file, failure.formatter)),
+        ));
+    }
+
+    // a single embed can't be longer than the limit for a whole message
+    let mut left_out = 0;
+    while embed_len(&embed) > MAX_TOTAL_LEN - 100 {
+        embed.fields.pop();
+        left_out += 1;
+    }
+    if left_out > 0 {
+        embed.description = Some(format!(
+            "{left_out} sections were left out because they were too long"
+        ));
+    }
+    embed
+}
+
+/// every extension that recovered from errors in one embed
+pub fn recovered_embed(recovered: &[String]) -> Embed {
+    let mut embed = embed("Recovered", LOW_COLOR);
+    embed.description = Some(truncate(&recovered.join("\n"), DESCRIPTION_LEN));
+    embed
+}
+
+/// every error in one embed. errors are formatted like the lines of the text message
+pub fn errors_embed(errors: &[String]) -> Embed {
+    let mut embed = embed("Errors", ERROR_COLOR);
+    embed.description = Some(truncate(&errors.join("\n"), DESCRIPTION_LEN));
+    embed
+}
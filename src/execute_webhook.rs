@@ -0,0 +1,195 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, StatusCode, Url};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{trace, warn};
+use twilight_model::channel::message::embed::Embed;
+
+use crate::attachments::Attachment;
+
+/// the number of times a webhook is executed again after being rate limited
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+
+#[derive(Deserialize)]
+pub struct WebhookConfig {
+    /// https://discord.com/api/webhooks/{id}/{token}
+    url: String,
+    /// overrides the webhook's default username
+    username: Option<String>,
+    /// overrides the webhook's default avatar
+    avatar_url: Option<String>,
+    /// the thread in the webhook's channel to send messages to
+    thread_id: Option<u64>,
+}
+
+impl Debug for WebhookConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the url contains the webhook's token
+        f.debug_struct("WebhookConfig")
+            .field("username", &self.username)
+            .field("thread_id", &self.thread_id)
+            .finish()
+    }
+}
+
+/// sends a message through a discord webhook. the message must fit in one discord message
+#[tracing::instrument(skip(client, content, embeds, attachments))]
+pub async fn execute_webhook(
+    client: &Client,
+    webhook: &WebhookConfig,
+    content: &str,
+    embeds: &[Embed],
+    attachments: &[Attachment],
+) -> Result<()> {
+    let mut url = Url::parse(&webhook.url).context("invalid webhook url")?;
+    // wait for the message to be created, so that errors are reported
+    url.query_pairs_mut().append_pair("wait", "true");
+    if let Some(thread_id) = webhook.thread_id {
+        url.query_pairs_mut()
+            .append_pair("thread_id", &thread_id.to_string());
+    }
+
+    let mut payload = json!({
+        "content": content,
+        "embeds": embeds,
+        "attachments": attachments
+            .iter()
+            .enumerate()
+            .map(|(id, attachment)| json!({ "id": id, "filename": attachment.filename }))
+            .collect::<Vec<_>>(),
+    });
+    if let Some(ref username) = webhook.username {
+        payload["username"] = username.as_str().into();
+    }
+    if let Some(ref avatar_url) = webhook.avatar_url {
+        payload["avatar_url"] = avatar_url.as_str().into();
+    }
+    let payload = payload.to_string();
+
+    let mut retries = 0;
+    loop {
+        // forms can't be cloned, so it is rebuilt for every attempt
+        let mut form = Form::new().text("payload_json", payload.clone());
+        for (id, attachment) in attachments.iter().enumerate() {
+            form = form.part(
+                format!("files[{id}]"),
+                Part::bytes(attachment.data.clone()).file_name(attachment.filename.clone()),
+            );
+        }
+
+        trace!("executing webhook");
+        let response = client
+            .post(url.clone())
+            .multipart(form)
+            .send()
+            .await
+            // the error would otherwise include the url, which contains the webhook's token
+            .map_err(reqwest::Error::without_url)
+            .context("couldn't execute webhook")?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RATE_LIMIT_RETRIES {
+            #[derive(Deserialize)]
+            struct RateLimit {
+                retry_after: f64,
+            }
+            let retry_after = response
+                .bytes()
+                .await
+                .ok()
+                .and_then(|body| serde_json::from_slice::<RateLimit>(&body).ok())
+                .map_or(1.0, |r| r.retry_after);
+            warn!(retry_after, "rate limited by discord, retrying");
+            tokio::time::sleep(Duration::from_secs_f64(retry_after.clamp(0.0, 60.0))).await;
+            retries += 1;
+            continue;
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("webhook responded with {status}: {body}");
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+
+    #[tokio::test]
+    async fn sends_payload_and_attachments() {
+        let (url, mut requests) = serve("{}").await;
+        let webhook = WebhookConfig {
+            url: format!("{url}/api/webhooks/1/token"),
+            username: Some("watcher".into()),
+            avatar_url: Some("https://example.com/avatar.png".into()),
+            thread_id: Some(42),
+        };
+        let attachments = [
+            Attachment {
+                filename: "a-1.0-1.1.diff".into(),
+                data: b"-old\n+new".to_vec(),
+            },
+            Attachment {
+                filename: "a-1.0-1.1.html".into(),
+                data: b"<p>report</p>".to_vec(),
+            },
+        ];
+        execute_webhook(&Client::new(), &webhook, "a updated", &[], &attachments)
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, "POST");
+        let url = Url::parse(&format!("http://localhost{}", request.path)).unwrap();
+        assert_eq!(url.path(), "/api/webhooks/1/token");
+        let query = url.query_pairs().into_owned().collect::<Vec<_>>();
+        assert!(query.contains(&("wait".into(), "true".into())));
+        assert!(query.contains(&("thread_id".into(), "42".into())));
+
+        let payload: serde_json::Value =
+            serde_json::from_str(&request.field("payload_json").unwrap()).unwrap();
+        assert_eq!(payload["content"], "a updated");
+        assert_eq!(payload["username"], "watcher");
+        assert_eq!(payload["avatar_url"], "https://example.com/avatar.png");
+        assert_eq!(
+            payload["attachments"],
+            json!([
+                { "id": 0, "filename": "a-1.0-1.1.diff" },
+                { "id": 1, "filename": "a-1.0-1.1.html" },
+            ])
+        );
+        assert!(request.multipart().contains(&(
+            "files[0]".into(),
+            Some("a-1.0-1.1.diff".into()),
+            "-old\n+new".into()
+        )));
+        assert!(request.multipart().contains(&(
+            "files[1]".into(),
+            Some("a-1.0-1.1.html".into()),
+            "<p>report</p>".into()
+        )));
+    }
+}
@@ -20,13 +20,22 @@ use std::fmt::Debug;
 
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+use crate::format_files::FormatterRule;
+
+#[derive(Default, Clone, Deserialize)]
 pub struct Extension {
     pub name: String,
     pub display_name: String,
     pub id: String,
     pub url: Option<String>,
     pub generate_diff: bool,
+    #[serde(default)]
+    pub formatters: Vec<FormatterRule>,
+    /// overrides the global ignore patterns
+    pub ignore: Option<Vec<String>>,
+    /// used to route update messages to notifiers, like "goguardian" or "alpha"
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 impl Debug for Extension {
@@ -47,6 +56,8 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "jfbecfmiegcjddenjhlbhlikcbfmnafd".into(),
             url: Some("https://deviceconsole.securly.com/dist/chrome/n.xml".into()),
             generate_diff: true,
+            tags: vec!["securly".into()],
+            ..Default::default()
         },
         Extension {
             name: "chromebooks".into(),
@@ -54,6 +65,8 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "iheobagjkfklnlikgihanlhcddjoihkg".into(),
             url: None,
             generate_diff: true,
+            tags: vec!["securly".into()],
+            ..Default::default()
         },
         Extension {
             name: "chromebooks-new".into(),
@@ -61,6 +74,8 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "joflmkccibkooplaeoinecjbmdebglab".into(),
             url: Some("https://extensions.securly.com/extensions.xml".into()),
             generate_diff: true,
+            tags: vec!["securly".into()],
+            ..Default::default()
         },
         Extension {
             name: "goguardian-stable".into(),
@@ -68,6 +83,8 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "haldlgldplgnggkjaafhelgiaglafanh".into(),
             url: Some("https://ext.goguardian.com/stable.xml".into()),
             generate_diff: true,
+            tags: vec!["goguardian".into(), "stable".into()],
+            ..Default::default()
         },
         Extension {
             name: "goguardian-alpha".into(),
@@ -75,6 +92,8 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "haldlgldplgnggkjaafhelgiaglafanh".into(),
             url: Some("https://ext.goguardian.com/alpha.xml".into()),
             generate_diff: true,
+            tags: vec!["goguardian".into(), "alpha".into()],
+            ..Default::default()
         },
         Extension {
             name: "blocksi".into(),
@@ -82,6 +101,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "ghlpmldmjjhmdgmneoaibbegkjjbonbk".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "iboss".into(),
@@ -89,6 +109,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "kmffehbidlalibfeklaefnckpidbodff".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "fortiguard".into(),
@@ -96,6 +117,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "igbgpehnbmhgdgjbhkkpedommgmfbeao".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "cisco".into(),
@@ -103,6 +125,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "jcdhmojfecjfmbdpchihbeilohgnbdci".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "netref".into(),
@@ -110,6 +133,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "khfdeghnhlpdfeenmdofgcbilkngngcp".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "contentkeeper".into(),
@@ -117,6 +141,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "jdogphakondfdmcanpapfahkdomaicfa".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "hapara".into(),
@@ -124,6 +149,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "kbohafcopfpigkjdimdcdgenlhkmhbnc".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "smoothwall".into(),
@@ -131,6 +157,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "jbldkhfglmgeihlcaeliadhipokhocnm".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "linewize".into(),
@@ -138,6 +165,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "ddfbkhpmcdbciejenfcolaaiebnjcbfc".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
         Extension {
             name: "lanschool".into(),
@@ -145,6 +173,7 @@ pub fn builtin_extensions() -> Vec<Extension> {
             id: "baleiojnjpgeojohhhfbichcodgljmnj".into(),
             url: None,
             generate_diff: true,
+            ..Default::default()
         },
     ]
 }
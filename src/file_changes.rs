@@ -0,0 +1,190 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tokio::process::Command;
+use walkdir::WalkDir;
+
+use crate::diff_binary::{describe_binary, diff_binary};
+use crate::{extensions::Extension, Config};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FileChange {
+    /// path of the file relative to the extracted extension directory
+    pub path: String,
+    pub kind: ChangeKind,
+    /// if the file matches an ignore pattern, in which case it isn't formatted or diffed
+    pub ignored: bool,
+}
+
+/// the extension's ignore patterns, or the global ignore patterns if the extension doesn't have any
+pub fn ignore_set(config: &Config, extension: &Extension) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in extension.ignore.as_ref().unwrap_or(&config.ignore) {
+        builder.add(Glob::new(pattern).with_context(|| format!("invalid ignore glob {pattern}"))?);
+    }
+    builder.build().context("couldn't build ignore glob set")
+}
+
+pub fn relative_path(path: &Path, dir: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+fn list_files(dir: &Path) -> BTreeMap<String, PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| (relative_path(e.path(), dir), e.into_path()))
+        .collect()
+}
+
+/// compares the files in two directories in ./crx, sorted by path
+#[tracing::instrument(skip(ignore))]
+pub async fn file_changes(
+    prev_dir: &str,
+    cur_dir: &str,
+    ignore: &GlobSet,
+) -> Result<Vec<FileChange>> {
+    let (prev_dir, cur_dir, ignore) = (
+        PathBuf::from(format!("./crx/{prev_dir}")),
+        PathBuf::from(format!("./crx/{cur_dir}")),
+        ignore.clone(),
+    );
+    tokio::task::spawn_blocking(move || {
+        let prev_files = list_files(&prev_dir);
+        let mut cur_files = list_files(&cur_dir);
+        let mut changes = vec![];
+        for (path, prev_path) in prev_files {
+            let kind = match cur_files.remove(&path) {
+                Some(cur_path) => {
+                    let prev = std::fs::read(&prev_path)
+                        .with_context(|| format!("couldn't read {}", prev_path.display()))?;
+                    let cur = std::fs::read(&cur_path)
+                        .with_context(|| format!("couldn't read {}", cur_path.display()))?;
+                    if prev == cur {
+                        continue;
+                    }
+                    ChangeKind::Modified
+                }
+                None => ChangeKind::Removed,
+            };
+            changes.push(FileChange {
+                ignored: ignore.is_match(&path),
+                path,
+                kind,
+            });
+        }
+        for path in cur_files.into_keys() {
+            changes.push(FileChange {
+                ignored: ignore.is_match(&path),
+                path,
+                kind: ChangeKind::Added,
+            });
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    })
+    .await
+    .unwrap()
+}
+
+/// diffs the files that changed between two directories in ./crx, skipping ignored files.
+/// the output is the same as `diff -U 10 -r`
+pub async fn diff_changes(prev_dir: &str, cur_dir: &str, changes: &[FileChange]) -> Result<String> {
+    let mut output = String::new();
+    for change in changes.iter().filter(|c| !c.ignored) {
+        let (parent, name) = match change.path.rsplit_once('/') {
+            Some((parent, name)) => (format!("/{parent}"), name),
+            None => ("".into(), change.path.as_str()),
+        };
+        match change.kind {
+            ChangeKind::Added => {
+                output.push_str(&format!("Only in ./{cur_dir}{parent}: {name}\n"));
+                if let Some(description) =
+                    describe_binary(&format!("./{cur_dir}/{}", change.path), true).await?
+                {
+                    output.push_str(&description);
+                }
+            }
+            ChangeKind::Removed => {
+                output.push_str(&format!("Only in ./{prev_dir}{parent}: {name}\n"));
+                if let Some(description) =
+                    describe_binary(&format!("./{prev_dir}/{}", change.path), false).await?
+                {
+                    output.push_str(&description);
+                }
+            }
+            ChangeKind::Modified => {
+                let prev_path = format!("./{prev_dir}/{}", change.path);
+                let cur_path = format!("./{cur_dir}/{}", change.path);
+                let diff = Command::new("diff")
+                    .arg("-U")
+                    .arg("10")
+                    .arg(&prev_path)
+                    .arg(&cur_path)
+                    .current_dir("./crx")
+                    .output()
+                    .await
+                    .context("couldn't get diff")?;
+                let stderr = String::from_utf8_lossy(&diff.stderr);
+                let stderr = stderr.trim();
+                if !stderr.is_empty() {
+                    eprintln!("{stderr}");
+                    bail!("diff did not exit successfully");
+                }
+                let diff = String::from_utf8_lossy(&diff.stdout);
+                if diff.starts_with("Binary files") {
+                    output.push_str(&diff_binary(&prev_path, &cur_path).await?);
+                } else {
+                    output.push_str(&format!("diff -U 10 -r {prev_path} {cur_path}\n"));
+                    output.push_str(&diff);
+                }
+            }
+        }
+    }
+    Ok(regex::Regex::new(r"\t\d\d\d\d-\d.*")
+        .unwrap()
+        .replace_all(&output, "")
+        .to_string())
+}
@@ -0,0 +1,252 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace, warn};
+use walkdir::WalkDir;
+
+/// file extensions that are searched for endpoints
+const SEARCHED_EXTENSIONS: &[&str] = &["js", "mjs", "cjs", "json", "html", "htm"];
+
+/// top level domains that a quoted string has to end with to be considered a hostname.
+/// without this, strings like "chrome.runtime" or "manifest.json" would be picked up.
+const KNOWN_TLDS: &[&str] = &[
+    "com",
+    "net",
+    "org",
+    "io",
+    "co",
+    "ai",
+    "app",
+    "dev",
+    "edu",
+    "gov",
+    "us",
+    "uk",
+    "ca",
+    "au",
+    "in",
+    "de",
+    "fr",
+    "nl",
+    "es",
+    "it",
+    "ch",
+    "se",
+    "no",
+    "jp",
+    "cn",
+    "ru",
+    "br",
+    "me",
+    "tv",
+    "info",
+    "biz",
+    "cloud",
+    "xyz",
+    "site",
+    "online",
+    "tech",
+    "services",
+    "ly",
+    "gg",
+    "sh",
+    "to",
+    "nz",
+    "ie",
+    "sg",
+    "hk",
+    "mx",
+    "za",
+    "school",
+    "education",
+    "network",
+    "link",
+    "page",
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Endpoints {
+    pub urls: BTreeSet<String>,
+    pub websockets: BTreeSet<String>,
+    pub hosts: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+pub struct EndpointChanges {
+    pub added: Endpoints,
+    pub removed: Endpoints,
+}
+
+impl Endpoints {
+    pub fn changes(&self, prev: &Endpoints) -> EndpointChanges {
+        fn difference(a: &BTreeSet<String>, b: &BTreeSet<String>) -> BTreeSet<String> {
+            a.difference(b).cloned().collect()
+        }
+        EndpointChanges {
+            added: Endpoints {
+                urls: difference(&self.urls, &prev.urls),
+                websockets: difference(&self.websockets, &prev.websockets),
+                hosts: difference(&self.hosts, &prev.hosts),
+            },
+            removed: Endpoints {
+                urls: difference(&prev.urls, &self.urls),
+                websockets: difference(&prev.websockets, &self.websockets),
+                hosts: difference(&prev.hosts, &self.hosts),
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty() && self.websockets.is_empty() && self.hosts.is_empty()
+    }
+
+    /// every endpoint, labeled with its kind, in the order hosts, urls, websockets
+    pub fn labeled(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        self.hosts
+            .iter()
+            .map(|h| ("host", h))
+            .chain(self.urls.iter().map(|u| ("url", u)))
+            .chain(self.websockets.iter().map(|w| ("websocket", w)))
+    }
+
+    fn add_url(&mut self, url: &str) {
+        let url = url.trim_end_matches(['.', ',', ';', ':']);
+        let Some((scheme, rest)) = url.split_once("://") else {
+            return;
+        };
+        let host = rest
+            .split(['/', ':', '?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if host.is_empty() {
+            return;
+        }
+        self.hosts.insert(host);
+        if scheme.eq_ignore_ascii_case("ws") || scheme.eq_ignore_ascii_case("wss") {
+            self.websockets.insert(url.into());
+        } else {
+            self.urls.insert(url.into());
+        }
+    }
+}
+
+impl EndpointChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn endpoints_path(name: &str, version: &str) -> String {
+    format!("./endpoints/{name}-{version}.json")
+}
+
+/// searches the extracted extension in ./crx/{name}-{version} for endpoints and stores them in ./endpoints
+#[tracing::instrument(err)]
+pub async fn find_endpoints(name: &str, version: &str) -> Result<Endpoints> {
+    let dir_path = format!("./crx/{name}-{version}");
+    let endpoints = tokio::task::spawn_blocking(move || search_dir(Path::new(&dir_path)))
+        .await
+        .unwrap();
+    debug!(
+        urls = endpoints.urls.len(),
+        websockets = endpoints.websockets.len(),
+        hosts = endpoints.hosts.len(),
+        "found endpoints"
+    );
+
+    tokio::fs::write(
+        endpoints_path(name, version),
+        serde_json::to_string_pretty(&endpoints).unwrap(),
+    )
+    .await
+    .context("couldn't write endpoints file")?;
+
+    Ok(endpoints)
+}
+
+/// loads the stored endpoints of a previously checked version.
+/// if they were never stored but the version is still extracted in ./crx, they are searched for again.
+#[tracing::instrument]
+pub async fn load_endpoints(name: &str, version: &str) -> Option<Endpoints> {
+    match tokio::fs::read(endpoints_path(name, version)).await {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(endpoints) => return Some(endpoints),
+            Err(error) => warn!(%error, "failed to deserialize endpoints file"),
+        },
+        Err(error) => trace!(%error, "failed to read endpoints file"),
+    }
+
+    if tokio::fs::try_exists(format!("./crx/{name}-{version}"))
+        .await
+        .unwrap_or(false)
+    {
+        find_endpoints(name, version).await.ok()
+    } else {
+        None
+    }
+}
+
+fn search_dir(dir: &Path) -> Endpoints {
+    let url_regex = Regex::new(
+        r#"(?i)\b(?:https?|wss?)://[a-z0-9\-._~%@\[\]]+(?::\d+)?(?:[/?#][^\s"'`<>\\(){}\[\]|^]*)?"#,
+    )
+    .unwrap();
+    let host_regex = Regex::new(
+        r#"["'`]((?:[a-zA-Z0-9](?:[a-zA-Z0-9\-]{0,61}[a-zA-Z0-9])?\.)+([a-zA-Z]{2,24}))["'`]"#,
+    )
+    .unwrap();
+
+    let mut endpoints = Endpoints::default();
+    for entry in WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| SEARCHED_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        })
+    {
+        let contents = match std::fs::read(entry.path()) {
+            Ok(c) => c,
+            Err(error) => {
+                warn!(%error, path = %entry.path().display(), "failed to read file");
+                continue;
+            }
+        };
+        let contents = String::from_utf8_lossy(&contents);
+
+        for url in url_regex.find_iter(&contents) {
+            endpoints.add_url(url.as_str());
+        }
+        for host in host_regex.captures_iter(&contents) {
+            if KNOWN_TLDS.contains(&host[2].to_lowercase().as_str()) {
+                endpoints.hosts.insert(host[1].to_lowercase());
+            }
+        }
+    }
+    endpoints
+}
@@ -0,0 +1,384 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::future::join_all;
+use futures_util::{stream, StreamExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, trace, warn};
+use walkdir::WalkDir;
+
+use crate::extensions::Extension;
+use crate::file_changes::{ignore_set, relative_path};
+use crate::pretty_print::{pretty_print_file, Language};
+use crate::{Config, PRETTIERRC_PATH};
+
+/// how many files are formatted at the same time
+const CONCURRENCY: usize = 5;
+
+/// how many prettier processes files are split between
+const PRETTIER_CHUNKS: usize = 5;
+
+/// file extensions that prettier is used for when it is the default formatter
+const PRETTIER_EXTENSIONS: &[&str] = &[
+    "js", "mjs", "cjs", "jsx", "ts", "tsx", "css", "scss", "less", "html", "htm", "vue", "json",
+    "md", "yaml", "yml",
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Formatter {
+    /// the formatter in pretty_print.rs
+    #[default]
+    Builtin,
+    /// prettier (https://prettier.io), which has to be on PATH
+    Prettier,
+    /// a command from a formatter rule
+    Command,
+    /// leave the file alone
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FormatterRule {
+    /// globs that are matched against the path of the file relative to the extension's directory
+    patterns: Vec<String>,
+    formatter: Formatter,
+    /// the command and its arguments for the command formatter
+    #[serde(default)]
+    command: Vec<String>,
+    /// timeout in seconds, overriding format_timeout
+    timeout: Option<u64>,
+}
+
+impl FormatterRule {
+    pub fn uses_prettier(&self) -> bool {
+        self.formatter == Formatter::Prettier
+    }
+}
+
+#[derive(Debug)]
+pub struct FormatFailure {
+    /// path of the file relative to the extracted extension directory
+    pub file: String,
+    pub formatter: String,
+    pub error: String,
+}
+
+struct Rules<'a> {
+    rules: Vec<(GlobSet, &'a FormatterRule)>,
+    default: Formatter,
+}
+
+impl<'a> Rules<'a> {
+    fn new(default: Formatter, rules: impl Iterator<Item = &'a FormatterRule>) -> Result<Self> {
+        if default == Formatter::Command {
+            bail!("formatter = \"command\" can only be used in formatter rules");
+        }
+        let mut compiled = vec![];
+        for rule in rules {
+            if rule.formatter == Formatter::Command && rule.command.is_empty() {
+                bail!("formatter rule {:?} has no command", rule.patterns);
+            }
+            let mut builder = GlobSetBuilder::new();
+            for pattern in &rule.patterns {
+                builder.add(Glob::new(pattern).with_context(|| format!("invalid glob {pattern}"))?);
+            }
+            compiled.push((builder.build().context("couldn't build glob set")?, rule));
+        }
+        Ok(Self {
+            rules: compiled,
+            default,
+        })
+    }
+
+    /// returns None if the file shouldn't be formatted
+    fn select(
+        &self,
+        relative: &str,
+        path: &Path,
+    ) -> Option<(Formatter, Option<&'a FormatterRule>)> {
+        if let Some((_, rule)) = self
+            .rules
+            .iter()
+            .find(|(globs, _)| globs.is_match(relative))
+        {
+            return (rule.formatter != Formatter::None).then_some((rule.formatter, Some(*rule)));
+        }
+        let supported = match self.default {
+            Formatter::Builtin => Language::from_path(path).is_some(),
+            Formatter::Prettier => path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| PRETTIER_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str())),
+            Formatter::Command | Formatter::None => false,
+        };
+        supported.then_some((self.default, None))
+    }
+}
+
+/// formats every file in dir_path in place, according to the extension's formatter rules, then the global
+/// formatter rules, then the default formatter. failures don't stop other files from being formatted
+#[tracing::instrument(skip(config), fields(extension = ?extension))]
+pub async fn format_files(
+    config: &Config,
+    extension: &Extension,
+    dir_path: String,
+) -> Result<Vec<FormatFailure>> {
+    let rules = Rules::new(
+        config.formatter,
+        extension.formatters.iter().chain(config.formatters.iter()),
+    )?;
+    let ignore = ignore_set(config, extension)?;
+
+    trace!("finding files to format");
+    let dir = PathBuf::from(&dir_path);
+    let mut files = tokio::task::spawn_blocking(move || {
+        let mut children = vec![];
+        for entry in WalkDir::new(dir_path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let len = entry
+                .metadata()
+                .with_context(|| format!("couldn't get metadata of {}", entry.path().display()))?
+                .len();
+            children.push((entry.path().to_path_buf(), len));
+        }
+        anyhow::Ok(children)
+    })
+    .await
+    .context("couldn't find files to format")??;
+    // start with the largest files so that they don't end up being formatted last
+    files.sort_by_key(|(_, len)| Reverse(*len));
+
+    let mut prettier_files = vec![];
+    let mut jobs = vec![];
+    for (path, len) in files {
+        let relative = relative_path(&path, &dir);
+        if ignore.is_match(&relative) {
+            continue;
+        }
+        let Some((formatter, rule)) = rules.select(&relative, &path) else {
+            continue;
+        };
+        let timeout = Duration::from_secs(
+            rule.and_then(|r| r.timeout)
+                .unwrap_or(config.format_timeout),
+        );
+        if formatter == Formatter::Prettier {
+            // prettier takes a while to start, so it formats files in batches instead of one at a time
+            prettier_files.push(((path, relative, timeout), len));
+            continue;
+        }
+        jobs.push(async move {
+            trace!(relative, ?formatter, "formatting");
+            let result = match formatter {
+                Formatter::Builtin => {
+                    let indent = config.indent;
+                    // the blocking thread can't be stopped, so on a timeout it's left to finish in the background
+                    let job = tokio::task::spawn_blocking(move || pretty_print_file(&path, indent));
+                    match tokio::time::timeout(timeout, job).await {
+                        Ok(Ok(result)) => result,
+                        Ok(Err(error)) => Err(anyhow!("formatter job failed: {error}")),
+                        Err(_) => Err(anyhow!("timed out after {} seconds", timeout.as_secs())),
+                    }
+                }
+                Formatter::Command => {
+                    run_formatter_command(&rule.unwrap().command, &path, timeout).await
+                }
+                Formatter::Prettier | Formatter::None => Ok(()),
+            };
+            result.map_err(|error| FormatFailure {
+                file: relative,
+                formatter: match formatter {
+                    Formatter::Command => rule.unwrap().command[0].clone(),
+                    _ => format!("{formatter:?}").to_lowercase(),
+                },
+                error: format!("{error:#}"),
+            })
+        });
+    }
+
+    // each file goes to the batch with the fewest bytes so far, so that the batches take about as long
+    let mut file_chunks = vec![vec![]; PRETTIER_CHUNKS];
+    for item in &prettier_files {
+        let chunk_with_least = file_chunks
+            .iter_mut()
+            .min_by_key(|chunk| chunk.iter().map(|c: &&(_, u64)| c.1).sum::<u64>())
+            .unwrap();
+        chunk_with_least.push(item);
+    }
+    let prettier_jobs = file_chunks
+        .into_iter()
+        .filter(|files| !files.is_empty())
+        .map(|files| run_prettier(files.into_iter().map(|(file, _)| file).collect()));
+
+    let (mut failures, prettier_failures) = futures_util::join!(
+        stream::iter(jobs)
+            .buffer_unordered(CONCURRENCY)
+            .filter_map(|result| async move { result.err() })
+            .collect::<Vec<_>>(),
+        join_all(prettier_jobs),
+    );
+    failures.extend(prettier_failures.into_iter().flatten());
+    for failure in &failures {
+        warn!(
+            file = failure.file,
+            formatter = failure.formatter,
+            "failed to format file: {}",
+            failure.error
+        );
+    }
+    debug!(failures = failures.len(), "done formatting");
+    Ok(failures)
+}
+
+fn prettier_command<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Command {
+    let mut command = Command::new("prettier");
+    command
+        .arg("--config")
+        .arg(PRETTIERRC_PATH)
+        .arg("--ignore-path=")
+        .arg("--write");
+    for path in paths {
+        command.arg(path);
+    }
+    command
+}
+
+/// formats a batch of files with one prettier process, which gets the sum of the files' timeouts. if it fails, the
+/// files are formatted again one at a time to find out which of them failed
+async fn run_prettier(files: Vec<&(PathBuf, String, Duration)>) -> Vec<FormatFailure> {
+    trace!(files = files.len(), "formatting with prettier");
+    let timeout = files.iter().map(|(_, _, timeout)| *timeout).sum();
+    let error = match run_command(
+        prettier_command(files.iter().map(|(path, _, _)| path)),
+        None,
+        timeout,
+    )
+    .await
+    {
+        Ok(output) => {
+            debug!(
+                "prettier output: {}",
+                String::from_utf8_lossy(&output).trim()
+            );
+            return vec![];
+        }
+        Err(error) => error,
+    };
+    if files.len() == 1 {
+        let (_, relative, _) = files[0];
+        return vec![FormatFailure {
+            file: relative.clone(),
+            formatter: "prettier".into(),
+            error: format!("{error:#}"),
+        }];
+    }
+
+    debug!(
+        error = format!("{error:#}"),
+        "prettier failed, retrying files one at a time"
+    );
+    let mut failures = vec![];
+    for (path, relative, timeout) in files {
+        if let Err(error) = run_command(prettier_command([path]), None, *timeout).await {
+            failures.push(FormatFailure {
+                file: relative.clone(),
+                formatter: "prettier".into(),
+                error: format!("{error:#}"),
+            });
+        }
+    }
+    failures
+}
+
+/// runs a command formatter. if an argument is `{file}`, it is replaced by the file's path and the command is expected
+/// to format the file in place. otherwise, the file is written to the command's stdin and replaced with its stdout
+async fn run_formatter_command(args: &[String], path: &Path, timeout: Duration) -> Result<()> {
+    let in_place = args.iter().any(|a| a == "{file}");
+    let mut command = Command::new(&args[0]);
+    for arg in &args[1..] {
+        if arg == "{file}" {
+            command.arg(path);
+        } else {
+            command.arg(arg);
+        }
+    }
+
+    if in_place {
+        run_command(command, None, timeout).await?;
+    } else {
+        let input = tokio::fs::read(path).await.context("couldn't read file")?;
+        let output = run_command(command, Some(input), timeout).await?;
+        tokio::fs::write(path, output)
+            .await
+            .context("couldn't write formatted file")?;
+    }
+    Ok(())
+}
+
+/// runs a command, killing it if it takes longer than timeout. returns its stdout if it exits successfully
+async fn run_command(
+    mut command: Command,
+    stdin: Option<Vec<u8>>,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn().context("couldn't spawn formatter")?;
+    if let Some(stdin) = stdin {
+        let mut child_stdin = child.stdin.take().unwrap();
+        // write in the background so a formatter that writes output before reading all of its input can't deadlock
+        tokio::spawn(async move {
+            if let Err(error) = child_stdin.write_all(&stdin).await {
+                trace!(%error, "failed to write to formatter stdin");
+            }
+        });
+    }
+
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .with_context(|| format!("timed out after {} seconds", timeout.as_secs()))?
+        .context("couldn't wait for formatter")?;
+    if !output.status.success() {
+        bail!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
@@ -20,19 +20,37 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use anyhow::{bail, Context, Result};
-use futures_util::future::join_all;
+use anyhow::{Context, Result};
+use globset::GlobSet;
 use reqwest::get;
 use tokio::{process::Command, sync::Mutex};
-use tracing::{debug, trace};
-use walkdir::WalkDir;
+use tracing::{debug, error, trace};
 
-use crate::{extensions::Extension, PRETTIERRC_PATH};
+use crate::compare_versions::find_version;
+use crate::file_changes::{diff_changes, file_changes, ignore_set, FileChange};
+use crate::find_endpoints::{find_endpoints, load_endpoints, EndpointChanges};
+use crate::format_files::{format_files, FormatFailure};
+use crate::html_report::html_report;
+use crate::manifest::{load_manifest, ManifestChanges};
+use crate::normalize_files::normalize_files;
+use crate::scan_secrets::{scan_secrets, Secret};
+use crate::unpack_bundles::{unpack_bundles, unpacked_dir};
+use crate::{extensions::Extension, Config};
 
 pub struct Update {
     pub prev_version: String,
     pub cur_version: String,
     pub diff: Option<String>,
+    /// the diff of the normalized files, if normalization is enabled
+    pub normalized_diff: Option<String>,
+    /// the self contained html version of diff, if html reports are enabled
+    pub html_report: Option<String>,
+    /// files that changed since the previous version, including ignored files
+    pub file_changes: Vec<FileChange>,
+    pub manifest_changes: Option<ManifestChanges>,
+    pub endpoint_changes: Option<EndpointChanges>,
+    pub secrets: Vec<Secret>,
+    pub format_failures: Vec<FormatFailure>,
 }
 
 impl Debug for Update {
@@ -44,9 +62,44 @@ impl Debug for Update {
     }
 }
 
-#[tracing::instrument(skip(versions), ret, err)]
+impl Update {
+    /// the file names and contents of the generated diffs
+    pub fn diff_files(&self, extension: &Extension) -> Vec<(String, &str)> {
+        let mut files = vec![];
+        if let Some(ref diff) = self.diff {
+            files.push((
+                format!(
+                    "{}-{}-{}.diff",
+                    extension.name, self.prev_version, self.cur_version
+                ),
+                diff.as_str(),
+            ));
+        }
+        if let Some(ref normalized_diff) = self.normalized_diff {
+            files.push((
+                format!(
+                    "{}-{}-{}.normalized.diff",
+                    extension.name, self.prev_version, self.cur_version
+                ),
+                normalized_diff.as_str(),
+            ));
+        }
+        if let Some(ref html_report) = self.html_report {
+            files.push((
+                format!(
+                    "{}-{}-{}.html",
+                    extension.name, self.prev_version, self.cur_version
+                ),
+                html_report.as_str(),
+            ));
+        }
+        files
+    }
+}
+
+#[tracing::instrument(skip(config, versions), ret, err)]
 pub async fn get_update(
-    force_generate_diffs: &Option<bool>,
+    config: &Config,
     extension: &Extension,
     versions: Arc<Mutex<HashMap<String, String>>>,
     cur_version: String,
@@ -92,113 +145,183 @@ pub async fn get_update(
             .await
             .context("couldn't delete crx file")?;
 
+        trace!("finding endpoints");
+        let endpoints = find_endpoints(&extension.name, &cur_version)
+            .await
+            .context("couldn't find endpoints")?;
+        let endpoint_changes = if prev_version != "None" {
+            load_endpoints(&extension.name, &prev_version)
+                .await
+                .map(|prev_endpoints| endpoints.changes(&prev_endpoints))
+        } else {
+            None
+        };
+
+        let secrets = if let Some(ref secrets_config) = config.secrets {
+            trace!("scanning for secrets");
+            scan_secrets(secrets_config, &extension.name, &prev_version, &cur_version)
+                .await
+                .context("couldn't scan for secrets")?
+        } else {
+            vec![]
+        };
+
         let mut generate_diff = extension.generate_diff;
-        if let Some(ref force_generate_diffs) = force_generate_diffs {
+        if let Some(ref force_generate_diffs) = config.force_generate_diffs {
             generate_diff = *force_generate_diffs;
         }
+        if prev_version != "None" && generate_diff {
+            // the diff would be empty without the previous version, so it is restored from the archive if it was
+            // removed from ./crx
+            find_version(config, &extension.name, &prev_version)
+                .await
+                .context("couldn't find the previous version to diff against")?;
+        }
+
+        let ignore = ignore_set(config, extension)?;
+        let mut format_failures = vec![];
         if generate_diff {
-            trace!("finding files to format");
-            let dir_path = format!("./crx/{}-{cur_version}", extension.name);
-            let mut files = tokio::task::spawn_blocking(move || {
-                let mut children = vec![];
-                for entry in WalkDir::new(dir_path)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|e| e.file_type().is_file())
-                {
-                    children.push((
-                        entry.path().display().to_string(),
-                        entry.metadata().unwrap().len(),
-                    ));
-                }
-                children
-            })
+            format_failures = format_files(
+                config,
+                extension,
+                format!("./crx/{}-{cur_version}", extension.name),
+            )
             .await
-            .unwrap();
-            files.sort_by(|(_, a), (_, b)| a.cmp(b));
-            files.reverse();
-            const CHUNKS: usize = 5;
-            let file_chunks = {
-                let mut chunks = vec![vec![]; CHUNKS];
-
-                for unsorted_chunk in files.chunks(CHUNKS) {
-                    for item in unsorted_chunk {
-                        let chunk_with_least = {
-                            let mut chunk_with_least = None;
-                            for chunk in chunks.iter_mut() {
-                                if chunk_with_least.is_none() {
-                                    chunk_with_least = Some(chunk);
-                                } else if chunk.iter().map(|c: &&(_, _)| c.1).sum::<u64>()
-                                    < chunk_with_least.as_ref().unwrap().iter().map(|c| c.1).sum()
-                                {
-                                    chunk_with_least = Some(chunk);
-                                }
-                            }
-                            chunk_with_least.unwrap()
-                        };
-                        chunk_with_least.push(item);
-                    }
-                }
+            .context("couldn't format files")?;
+        }
 
-                chunks
-            };
-            trace!(?file_chunks, "formatting");
-            let children = file_chunks
-                .into_iter()
-                .map(|files| {
-                    let mut command = Command::new("prettier");
-                    command
-                        .arg("--config")
-                        .arg(PRETTIERRC_PATH)
-                        .arg("--ignore-path=")
-                        .arg("--write");
-                    for (file, _) in files {
-                        command.arg(file);
-                    }
-                    command.output()
-                })
-                .collect::<Vec<_>>();
-            join_all(children)
+        let unpack_config = config.unpack.as_ref().filter(|_| generate_diff);
+        if let Some(unpack_config) = unpack_config {
+            trace!("unpacking bundles");
+            unpack_bundles(unpack_config, &ignore, &extension.name, &cur_version)
                 .await
-                .into_iter()
-                .filter_map(|c| c.ok())
-                .for_each(|o| print!("{}", String::from_utf8_lossy(&o.stdout)));
+                .context("couldn't unpack bundles")?;
+            format_failures.extend(
+                format_files(
+                    config,
+                    extension,
+                    unpacked_dir(&extension.name, &cur_version),
+                )
+                .await
+                .context("couldn't format unpacked files")?,
+            );
+
+            // versions from before unpacking was enabled have to be unpacked too so they can be diffed
+            if prev_version != "None"
+                && !tokio::fs::try_exists(unpacked_dir(&extension.name, &prev_version))
+                    .await
+                    .context("couldn't check if previous version was unpacked")?
+            {
+                unpack_bundles(unpack_config, &ignore, &extension.name, &prev_version)
+                    .await
+                    .context("couldn't unpack previous version's bundles")?;
+                format_files(
+                    config,
+                    extension,
+                    unpacked_dir(&extension.name, &prev_version),
+                )
+                .await
+                .context("couldn't format previous version's unpacked files")?;
+            }
         }
 
+        let prev_dir = format!("{}-{prev_version}", extension.name);
+        let cur_dir = format!("{}-{cur_version}", extension.name);
+        let file_changes = if prev_version != "None"
+            && tokio::fs::try_exists(format!("./crx/{prev_dir}"))
+                .await
+                .context("couldn't check if previous version exists")?
+        {
+            trace!("comparing files");
+            file_changes(&prev_dir, &cur_dir, &ignore)
+                .await
+                .context("couldn't compare files")?
+        } else {
+            vec![]
+        };
+        let manifest_changes = if prev_version != "None" {
+            match (
+                load_manifest(&extension.name, &prev_version).await,
+                load_manifest(&extension.name, &cur_version).await,
+            ) {
+                (Ok(prev_manifest), Ok(cur_manifest)) => Some(cur_manifest.changes(&prev_manifest)),
+                (Err(error), _) | (_, Err(error)) => {
+                    debug!("couldn't compare manifests: {error:#}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         if prev_version != "None" && generate_diff {
             trace!("getting diff");
-            let diff = Command::new("diff")
-                .arg("-U")
-                .arg("10")
-                .arg("-r")
-                .arg(format!("./{}-{prev_version}", extension.name))
-                .arg(format!("./{}-{cur_version}", extension.name))
-                .current_dir("./crx")
-                .output()
-                .await
-                .context("couldn't get diff")?;
-            let stderr = String::from_utf8_lossy(&diff.stderr);
-            let stderr = stderr.trim();
-            if !stderr.is_empty() {
-                eprintln!("{stderr}");
-                bail!("diff did not exit successfully");
+            let mut diff = diff_changes(&prev_dir, &cur_dir, &file_changes).await?;
+            if unpack_config.is_some() {
+                diff.push_str(
+                    &diff_dirs(
+                        &format!("{prev_dir}-unpacked"),
+                        &format!("{cur_dir}-unpacked"),
+                        &ignore,
+                    )
+                    .await?,
+                );
+            }
+
+            let normalized_diff = if let Some(ref normalize_config) = config.normalize {
+                trace!("normalizing files");
+                for version in [&prev_version, &cur_version] {
+                    normalize_files(normalize_config, &extension.name, version)
+                        .await
+                        .context("couldn't normalize files")?;
+                }
+                Some(
+                    diff_dirs(
+                        &format!("{prev_dir}-normalized"),
+                        &format!("{cur_dir}-normalized"),
+                        &ignore,
+                    )
+                    .await?,
+                )
             } else {
-                let diff = String::from_utf8_lossy(&diff.stdout);
-                let diff = regex::Regex::new(r"\t\d\d\d\d-\d.*")
-                    .unwrap()
-                    .replace_all(&diff, "");
-                Ok(Some(Update {
-                    prev_version,
-                    cur_version,
-                    diff: Some(diff.to_string()),
-                }))
+                None
+            };
+
+            let mut update = Update {
+                prev_version,
+                cur_version,
+                diff: Some(diff),
+                normalized_diff,
+                html_report: None,
+                file_changes,
+                manifest_changes,
+                endpoint_changes,
+                secrets,
+                format_failures,
+            };
+            if config.html_report {
+                update.html_report = html_report(extension, &update);
+            }
+            // the update is still reported if the diffs can't be saved, since the new version has already been unpacked
+            for (filename, diff) in update.diff_files(extension) {
+                if let Err(error) = tokio::fs::write(format!("./diff/{filename}"), diff).await {
+                    error!(%error, "couldn't write {filename}");
+                }
             }
+            Ok(Some(update))
         } else {
             trace!("skipping diff");
             Ok(Some(Update {
                 prev_version,
                 cur_version,
                 diff: None,
+                normalized_diff: None,
+                html_report: None,
+                file_changes,
+                manifest_changes,
+                endpoint_changes,
+                secrets,
+                format_failures,
             }))
         }
     } else {
@@ -206,3 +329,11 @@ pub async fn get_update(
         Ok(None)
     }
 }
+
+/// diffs two directories in ./crx, skipping ignored files
+async fn diff_dirs(prev_dir: &str, cur_dir: &str, ignore: &GlobSet) -> Result<String> {
+    let changes = file_changes(prev_dir, cur_dir, ignore)
+        .await
+        .context("couldn't compare files")?;
+    diff_changes(prev_dir, cur_dir, &changes).await
+}
@@ -0,0 +1,423 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! renders a diff as a single self contained html file, with a manifest summary, a file tree and side by side diffs
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::extensions::Extension;
+use crate::get_update::Update;
+use crate::tokenize_js::{tokenize_js, TokenKind};
+
+/// hunks with more lines than this are collapsed
+const LARGE_HUNK_LINES: usize = 80;
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 0 auto; max-width: 1600px; padding: 16px; background: #fff; color: #1f2328; }
+h1 { font-size: 1.5em; } h2 { font-size: 1.2em; margin-top: 1.5em; }
+code, pre, td.code { font-family: ui-monospace, Consolas, monospace; font-size: 12px; }
+.added { color: #1a7f37; } .removed { color: #cf222e; } .muted { color: #656d76; }
+ul.tree { list-style: none; padding-left: 1.2em; } ul.tree li { margin: 2px 0; }
+details.file { border: 1px solid #d0d7de; border-radius: 6px; margin: 12px 0; }
+details.file > summary { background: #f6f8fa; padding: 6px 10px; cursor: pointer; font-family: ui-monospace, Consolas, monospace; }
+details.hunk > summary { background: #ddf4ff; padding: 2px 10px; cursor: pointer; font-family: ui-monospace, Consolas, monospace; font-size: 12px; color: #656d76; }
+table.diff { border-collapse: collapse; width: 100%; table-layout: fixed; }
+table.diff td { padding: 0 6px; vertical-align: top; white-space: pre-wrap; word-break: break-all; }
+table.diff td.num { width: 4em; text-align: right; color: #656d76; user-select: none; }
+td.del { background: #ffebe9; } td.add { background: #e6ffec; } td.empty { background: #f6f8fa; }
+table.manifest td { padding: 2px 8px; vertical-align: top; }
+.binary { padding: 6px 10px; white-space: pre-wrap; }
+.k { color: #cf222e; } .s { color: #0a3069; } .n { color: #0550ae; } .c { color: #6e7781; font-style: italic; } .r { color: #116329; } .t { color: #116329; }
+"#;
+
+#[derive(Default)]
+struct FileDiff {
+    path: String,
+    /// "modified", "added", "removed", "binary" or "ignored"
+    status: &'static str,
+    hunks: Vec<Hunk>,
+    /// extra lines, like the sizes and hashes of binary files
+    details: Vec<String>,
+    added: usize,
+    removed: usize,
+}
+
+struct Hunk {
+    header: String,
+    old_start: usize,
+    new_start: usize,
+    lines: Vec<(char, String)>,
+}
+
+/// strips `./{name}-{version}` from a path in a diff, keeping the unpacked or normalized tree it came from
+fn display_path(path: &str) -> String {
+    let path = path.trim_start_matches("./");
+    let (dir, rest) = path.split_once('/').unwrap_or((path, ""));
+    for tree in ["unpacked", "normalized"] {
+        if dir.ends_with(&format!("-{tree}")) {
+            return format!("{tree}/{rest}");
+        }
+    }
+    rest.to_string()
+}
+
+fn parse_diff(diff: &str, prev_dir: &str) -> Vec<FileDiff> {
+    let hunk_header = Regex::new(r"^@@ -(\d+)(?:,\d+)? \+(\d+)(?:,\d+)? @@").unwrap();
+    let mut files: Vec<FileDiff> = vec![];
+    for line in diff.lines() {
+        if let Some(paths) = line.strip_prefix("diff -U 10 -r ") {
+            let cur_path = paths.rsplit(' ').next().unwrap_or_default();
+            files.push(FileDiff {
+                path: display_path(cur_path),
+                status: "modified",
+                ..Default::default()
+            });
+        } else if let Some(only) = line.strip_prefix("Only in ") {
+            let (dir, name) = only.split_once(": ").unwrap_or((only, ""));
+            let tree = dir
+                .trim_start_matches("./")
+                .split('/')
+                .next()
+                .unwrap_or_default();
+            let tree = tree
+                .strip_suffix("-unpacked")
+                .or_else(|| tree.strip_suffix("-normalized"))
+                .unwrap_or(tree);
+            let status = if tree == prev_dir { "removed" } else { "added" };
+            files.push(FileDiff {
+                path: display_path(&format!("{dir}/{name}")),
+                status,
+                ..Default::default()
+            });
+        } else if let Some(paths) = line.strip_prefix("Binary files ") {
+            let paths = paths.trim_end_matches(" differ");
+            let cur_path = paths.rsplit(" and ").next().unwrap_or_default();
+            files.push(FileDiff {
+                path: display_path(cur_path),
+                status: "binary",
+                ..Default::default()
+            });
+        } else if let Some(file) = files.last_mut() {
+            if file.status == "binary" && line.starts_with("  ") {
+                file.details.push(line.trim().to_string());
+            } else if let Some(captures) = hunk_header.captures(line) {
+                file.hunks.push(Hunk {
+                    header: line.to_string(),
+                    old_start: captures[1].parse().unwrap_or(1),
+                    new_start: captures[2].parse().unwrap_or(1),
+                    lines: vec![],
+                });
+            } else if let Some(hunk) = file.hunks.last_mut() {
+                let mut chars = line.chars();
+                let kind = chars.next().unwrap_or(' ');
+                if matches!(kind, ' ' | '-' | '+') {
+                    match kind {
+                        '-' => file.removed += 1,
+                        '+' => file.added += 1,
+                        _ => {}
+                    }
+                    hunk.lines.push((kind, chars.as_str().to_string()));
+                }
+            }
+        }
+    }
+    files
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// highlights a single line. lines are highlighted on their own, so multi line comments and strings aren't always right
+fn highlight(path: &str, line: &str) -> String {
+    let extension = path
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "js" | "mjs" | "cjs" | "jsx" | "ts" | "tsx" | "json" | "css" => {
+            let mut highlighted = String::with_capacity(line.len());
+            let mut last = 0;
+            for token in tokenize_js(line) {
+                let class = match token.kind {
+                    TokenKind::Word if token.is_keyword() => "k",
+                    TokenKind::String | TokenKind::Template => "s",
+                    TokenKind::Number => "n",
+                    TokenKind::LineComment | TokenKind::BlockComment => "c",
+                    TokenKind::Regex => "r",
+                    _ => continue,
+                };
+                highlighted.push_str(&escape(&line[last..token.start]));
+                let _ = write!(
+                    highlighted,
+                    "<span class=\"{class}\">{}</span>",
+                    escape(token.text)
+                );
+                last = token.start + token.text.len();
+            }
+            highlighted.push_str(&escape(&line[last..]));
+            highlighted
+        }
+        "html" | "htm" | "xml" | "svg" => {
+            static TAG: OnceLock<Regex> = OnceLock::new();
+            let tag =
+                TAG.get_or_init(|| Regex::new(r"</?[A-Za-z][^>]*>?|<!--.*?(?:-->|$)").unwrap());
+            let mut highlighted = String::with_capacity(line.len());
+            let mut last = 0;
+            for found in tag.find_iter(line) {
+                let class = if found.as_str().starts_with("<!--") {
+                    "c"
+                } else {
+                    "t"
+                };
+                highlighted.push_str(&escape(&line[last..found.start()]));
+                let _ = write!(
+                    highlighted,
+                    "<span class=\"{class}\">{}</span>",
+                    escape(found.as_str())
+                );
+                last = found.end();
+            }
+            highlighted.push_str(&escape(&line[last..]));
+            highlighted
+        }
+        _ => escape(line),
+    }
+}
+
+fn render_hunk(html: &mut String, path: &str, hunk: &Hunk) {
+    let _ = write!(
+        html,
+        "<details class=\"hunk\"{}><summary>{} ({} lines)</summary><table class=\"diff\">",
+        if hunk.lines.len() > LARGE_HUNK_LINES {
+            ""
+        } else {
+            " open"
+        },
+        escape(&hunk.header),
+        hunk.lines.len()
+    );
+    let (mut old_line, mut new_line) = (hunk.old_start, hunk.new_start);
+    let mut removed: Vec<(usize, &str)> = vec![];
+    let mut added: Vec<(usize, &str)> = vec![];
+    let cell = |number: Option<usize>, text: Option<&str>, class: &str| match (number, text) {
+        (Some(number), Some(text)) => format!(
+            "<td class=\"num\">{number}</td><td class=\"code {class}\">{}</td>",
+            highlight(path, text)
+        ),
+        _ => "<td class=\"num\"></td><td class=\"code empty\"></td>".to_string(),
+    };
+    let flush =
+        |html: &mut String, removed: &mut Vec<(usize, &str)>, added: &mut Vec<(usize, &str)>| {
+            for i in 0..removed.len().max(added.len()) {
+                let (old, new) = (removed.get(i), added.get(i));
+                let _ = write!(
+                    html,
+                    "<tr>{}{}</tr>",
+                    cell(old.map(|l| l.0), old.map(|l| l.1), "del"),
+                    cell(new.map(|l| l.0), new.map(|l| l.1), "add")
+                );
+            }
+            removed.clear();
+            added.clear();
+        };
+    for (kind, text) in &hunk.lines {
+        match kind {
+            '-' => {
+                removed.push((old_line, text));
+                old_line += 1;
+            }
+            '+' => {
+                added.push((new_line, text));
+                new_line += 1;
+            }
+            _ => {
+                flush(html, &mut removed, &mut added);
+                let _ = write!(
+                    html,
+                    "<tr>{}{}</tr>",
+                    cell(Some(old_line), Some(text), ""),
+                    cell(Some(new_line), Some(text), "")
+                );
+                old_line += 1;
+                new_line += 1;
+            }
+        }
+    }
+    flush(html, &mut removed, &mut added);
+    html.push_str("</table></details>");
+}
+
+#[derive(Default)]
+struct TreeNode<'a> {
+    children: BTreeMap<&'a str, TreeNode<'a>>,
+    /// index of the file and the file
+    file: Option<(usize, &'a FileDiff)>,
+}
+
+fn render_tree(html: &mut String, node: &TreeNode) {
+    html.push_str("<ul class=\"tree\">");
+    for (name, child) in &node.children {
+        html.push_str("<li>");
+        match child.file {
+            Some((i, file)) if child.children.is_empty() => {
+                let _ = write!(
+                    html,
+                    "<a href=\"#file-{i}\">{}</a> <span class=\"muted\">{}</span>",
+                    escape(name),
+                    file.status
+                );
+                if file.added > 0 || file.removed > 0 {
+                    let _ = write!(
+                        html,
+                        " <span class=\"added\">+{}</span> <span class=\"removed\">-{}</span>",
+                        file.added, file.removed
+                    );
+                }
+            }
+            _ => {
+                let _ = write!(html, "{}/", escape(name));
+                render_tree(html, child);
+            }
+        }
+        html.push_str("</li>");
+    }
+    html.push_str("</ul>");
+}
+
+fn render_manifest(html: &mut String, update: &Update) {
+    let Some(ref changes) = update.manifest_changes else {
+        return;
+    };
+    html.push_str("<h2>Manifest</h2>");
+    if changes.is_empty() {
+        html.push_str("<p class=\"muted\">no changes besides the version</p>");
+        return;
+    }
+    html.push_str("<table class=\"manifest\">");
+    for permission in &changes.added_permissions {
+        let _ = write!(
+            html,
+            "<tr><td class=\"added\">added</td><td><code>{}</code></td></tr>",
+            escape(permission)
+        );
+    }
+    for permission in &changes.removed_permissions {
+        let _ = write!(
+            html,
+            "<tr><td class=\"removed\">removed</td><td><code>{}</code></td></tr>",
+            escape(permission)
+        );
+    }
+    for (key, prev, cur) in &changes.changed_keys {
+        let _ = write!(
+            html,
+            "<tr><td>changed</td><td><code>{}</code>: <code class=\"removed\">{}</code> -&gt; <code class=\"added\">{}</code></td></tr>",
+            escape(key),
+            escape(prev.as_deref().unwrap_or("(none)")),
+            escape(cur.as_deref().unwrap_or("(none)"))
+        );
+    }
+    html.push_str("</table>");
+}
+
+/// renders the update's diff. returns None if there is no diff
+pub fn html_report(extension: &Extension, update: &Update) -> Option<String> {
+    let diff = update.diff.as_ref()?;
+    let mut files = parse_diff(diff, &format!("{}-{}", extension.name, update.prev_version));
+    for change in update.file_changes.iter().filter(|c| c.ignored) {
+        files.push(FileDiff {
+            path: change.path.clone(),
+            status: "ignored",
+            ..Default::default()
+        });
+    }
+
+    let title = format!(
+        "{}: {} -> {}",
+        extension.display_name, update.prev_version, update.cur_version
+    );
+    let mut html = format!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><title>{}</title><style>{STYLE}</style></head><body><h1>{}</h1>",
+        escape(&title),
+        escape(&title)
+    );
+    let _ = write!(
+        html,
+        "<p class=\"muted\">{} files changed, <span class=\"added\">+{}</span> <span class=\"removed\">-{}</span>. generated by extension-version-watcher {}</p>",
+        files.len(),
+        files.iter().map(|f| f.added).sum::<usize>(),
+        files.iter().map(|f| f.removed).sum::<usize>(),
+        env!("CARGO_PKG_VERSION")
+    );
+    render_manifest(&mut html, update);
+
+    html.push_str("<h2>Files</h2>");
+    let mut tree = TreeNode::default();
+    for (i, file) in files.iter().enumerate() {
+        let mut node = &mut tree;
+        for part in file.path.split('/') {
+            node = node.children.entry(part).or_default();
+        }
+        node.file = Some((i, file));
+    }
+    render_tree(&mut html, &tree);
+
+    html.push_str("<h2>Diff</h2>");
+    for (i, file) in files.iter().enumerate() {
+        if file.status == "ignored" {
+            continue;
+        }
+        let _ = write!(
+            html,
+            "<details class=\"file\" id=\"file-{i}\" open><summary>{} <span class=\"muted\">{}</span> <span class=\"added\">+{}</span> <span class=\"removed\">-{}</span></summary>",
+            escape(&file.path),
+            file.status,
+            file.added,
+            file.removed
+        );
+        if !file.details.is_empty() {
+            let _ = write!(
+                html,
+                "<div class=\"binary\">{}</div>",
+                escape(&file.details.join("\n"))
+            );
+        }
+        for hunk in &file.hunks {
+            render_hunk(&mut html, &file.path, hunk);
+        }
+        html.push_str("</details>");
+    }
+    html.push_str("</body></html>\n");
+    Some(html)
+}
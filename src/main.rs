@@ -19,96 +19,144 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use futures_util::future::join_all;
 use serde::Deserialize;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tracing::{debug, error, info, warn};
 
+mod archive_versions;
+mod attachments;
 mod check_extension;
+mod classify_update;
+mod compare_versions;
+mod diff_binary;
+mod discord_embeds;
+mod execute_webhook;
 mod extensions;
+mod file_changes;
+mod find_endpoints;
+mod format_files;
 mod get_update;
+mod html_report;
+mod manifest;
+mod normalize_files;
+mod notify;
+mod post_webhook;
+mod pretty_print;
+mod record_history;
+mod render_template;
+mod scan_secrets;
+mod send_email;
+mod send_push;
 mod send_to_discord;
-
+mod send_to_matrix;
+mod send_to_slack;
+mod send_to_teams;
+mod send_to_telegram;
+mod serve_api;
+mod serve_dashboard;
+#[cfg(test)]
+mod test_server;
+mod tokenize_js;
+mod track_errors;
+mod unpack_bundles;
+mod write_changelog;
+mod write_feeds;
+
+use crate::archive_versions::{archive_versions, ArchiveConfig};
+use crate::attachments::AttachmentsConfig;
 use crate::check_extension::check_extension;
+use crate::execute_webhook::WebhookConfig;
 use crate::extensions::{builtin_extensions, Extension};
+use crate::format_files::{Formatter, FormatterRule};
 use crate::get_update::{get_update, Update};
-use crate::send_to_discord::send_to_discord;
+use crate::normalize_files::NormalizeConfig;
+use crate::notify::{Notifier, NotifierConfig, Report, RoutedNotifier};
+use crate::record_history::record_history;
+use crate::scan_secrets::SecretsConfig;
+use crate::serve_dashboard::{run_server, ServerConfig, ServerState};
+use crate::track_errors::{track_errors, ErrorsConfig};
+use crate::unpack_bundles::UnpackConfig;
+use crate::write_changelog::write_changelogs;
+use crate::write_feeds::{write_feeds, FeedConfig};
 
 const fn default_true() -> bool {
     true
 }
 
+const fn default_indent() -> usize {
+    2
+}
+
+const fn default_format_timeout() -> u64 {
+    60
+}
+
+fn default_ignore() -> Vec<String> {
+    vec!["_metadata/**".into()]
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     #[serde(default = "default_true")]
     use_builtin_extensions: bool,
     force_generate_diffs: Option<bool>,
+    #[serde(default)]
+    formatter: Formatter,
+    #[serde(default = "default_indent")]
+    indent: usize,
+    #[serde(default)]
+    formatters: Vec<FormatterRule>,
+    #[serde(default = "default_format_timeout")]
+    format_timeout: u64,
+    #[serde(default = "default_ignore")]
+    ignore: Vec<String>,
+    #[serde(default)]
+    html_report: bool,
+    #[serde(default)]
+    changelog: bool,
     extra_extensions: Option<Vec<Extension>>,
-    discord: Option<DiscordConfig>,
+    discord: Option<RoutedNotifier<DiscordConfig>>,
+    #[serde(default)]
+    notifiers: Vec<RoutedNotifier>,
+    secrets: Option<SecretsConfig>,
+    errors: Option<ErrorsConfig>,
+    feed: Option<FeedConfig>,
+    archive: Option<ArchiveConfig>,
+    server: Option<ServerConfig>,
+    unpack: Option<UnpackConfig>,
+    normalize: Option<NormalizeConfig>,
 }
 
 #[derive(Deserialize)]
 pub struct DiscordConfig {
-    token: String,
+    token: Option<String>,
+    #[serde(default)]
     channel_ids: Vec<u64>,
+    #[serde(default)]
+    webhooks: Vec<WebhookConfig>,
+    /// if updates and errors should be sent as embeds instead of as text
+    #[serde(default)]
+    embeds: bool,
+    #[serde(flatten)]
+    attachments: AttachmentsConfig,
 }
 
 impl Debug for DiscordConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DiscordConfig")
             .field("channel_ids", &self.channel_ids)
+            .field("webhooks", &self.webhooks)
             .finish()
     }
 }
 
 const CONFIG_EXAMPLE_PATH: &str = "./config.example.toml";
 const CONFIG_PATH: &str = "./config.toml";
-const DEFAULT_CONFIG: &str = r#"# config file for extension-version-watcher
-# this file must be named config.toml and must be located in the current working directory when extension-version-watcher is run
-
-# to customize logging, use the RUST_LOG environment variable. it should be a comma separated list of one of:
-# - `foo=trace` (TARGET=LEVEL)
-# - `foo[{bar,baz}]=info` (TARGET[{FIELD,+}]=LEVEL)
-# - `trace` (bare LEVEL)
-# - `foo` (bare TARGET)
-# RUST_LOG defaults to `extension_version_watcher=trace`
-# if you just want less verbose logging, set RUST_LOG to `extension_version_watcher=info`
-
-
-# if you don't want the builtin extensions to be checked, uncomment the following line. defaults to true
-#use_builtin_extensions = false
-
-# if true, diffs will always be generated for all extensions.
-# if false, diffs will never be generated for any extensions.
-# if not specified, diffs will be generated according to extension specific settings.
-# diff generation currently requires prettier (https://prettier.io) to be on PATH
-# if you would like to use a custom prettier config, simply create .prettierrc.json in the current working directory. extension-version-watcher will see this and use it instead of the builtin config.
-#force_generate_diffs = false
-
-# extra extensions to add to the extension list
-# template / format (you can also specify extra extensions as a normal array, but toml doesn't allow inline tables to have newlines so each extension is limited to 1 line):
-#[[extra_extensions]]
-#name = "" # the name of the extension. this should only contain alphanumeric characters, underscores and hyphens. it will be used for directory and file names, as well as keys for versions.toml
-#          # after you set this value, do not change it. it will cause the extension's previously checked version to be reset.
-#display_name = "" # the display name of the extension. this will show up in logs and in the update messages that are sent to discord
-#id = "" # the chrome extension id
-##url = "" # (optional) the chrome extension update URL to use when checking for updates.
-#          # this should resolve to an XML file that has the chrome extension update format.
-#          # if url is not specified, the chrome webstore is searched for an extension with the specified id
-#generate_diff = true # if a diff should be automatically generated. can be overridden by force_generate_diffs
-
-
-# if you comment the following line, update messages will not be sent to discord and you will not need to specify discord.token and discord.channel_ids
-[discord]
-
-# the bot token. must be a string
-#token = ""
-
-# discord channel IDs to send the update messages to. if you want to send update messages to discord, make sure to set this. must be an array of numbers that are >0
-#channel_ids = []
-"#;
+const DEFAULT_CONFIG: &str = include_str!("../config.example.toml");
 
 const VERSIONS_PATH: &str = "./versions.toml";
 const VERSIONS_HEADER: &str = r#"# versions file for extension-version-watcher
@@ -159,6 +207,9 @@ async fn main_error_net() -> Result<()> {
     tokio::fs::create_dir_all("./diff")
         .await
         .context("couldn't create diff dir")?;
+    tokio::fs::create_dir_all("./endpoints")
+        .await
+        .context("couldn't create endpoints dir")?;
 
     if tokio::fs::try_exists(CONFIG_EXAMPLE_PATH)
         .await
@@ -181,24 +232,34 @@ async fn main_error_net() -> Result<()> {
     let config = tokio::fs::read_to_string(CONFIG_PATH)
         .await
         .context("failed to read config.toml")?;
-    let config: Config = toml::from_str(&config).context("failed to deserialize config.toml")?;
-
-    let versions: HashMap<String, String> = match tokio::fs::read_to_string(VERSIONS_PATH).await {
-        Ok(v) => toml::from_str(&v).context("failed to deserialize versions.toml")?,
-        Err(error) => {
-            warn!(%error, "failed to read versions.toml, versions will be empty");
-            HashMap::new()
-        }
-    };
-    debug!(?versions);
-    let versions = Arc::new(Mutex::new(versions));
+    let mut config: Config =
+        toml::from_str(&config).context("failed to deserialize config.toml")?;
+    // [discord] is the same as a discord notifier
+    let mut notifiers = config
+        .discord
+        .take()
+        .map(|discord| discord.map(NotifierConfig::Discord))
+        .into_iter()
+        .chain(std::mem::take(&mut config.notifiers))
+        .collect::<Vec<_>>();
+    for notifier in &mut notifiers {
+        notifier
+            .notifier
+            .validate()
+            .context("invalid notifier in config.toml")?;
+        notifier
+            .template
+            .load()
+            .await
+            .context("invalid notifier in config.toml")?;
+    }
 
     let mut extensions = if config.use_builtin_extensions {
         builtin_extensions()
     } else {
         vec![]
     };
-    if let Some(extra_extensions) = config.extra_extensions {
+    if let Some(extra_extensions) = config.extra_extensions.take() {
         extensions.reserve(extra_extensions.len());
         for extension in extra_extensions {
             info!(?extension, "adding extra extension");
@@ -206,7 +267,77 @@ async fn main_error_net() -> Result<()> {
         }
     }
 
-    let tmp_prettierrc = if !tokio::fs::try_exists(PRETTIERRC_PATH)
+    for notifier in &notifiers {
+        for name in notifier.route.unknown_extensions(&extensions) {
+            warn!(
+                extension = name,
+                "a notifier is routed an extension that isn't checked"
+            );
+        }
+    }
+
+    let server = config.server.take();
+    let config = Arc::new(config);
+    let Some(server) = server else {
+        return run_checks(&config, &extensions, &notifiers).await;
+    };
+
+    // with a server, the program keeps running and checks every interval
+    let extensions = Arc::new(extensions);
+    let check_now = Arc::new(Notify::new());
+    let state = ServerState {
+        config: Arc::clone(&config),
+        extensions: Arc::clone(&extensions),
+        api_token: server.api_token.as_deref().map(Arc::from),
+        check_now: Arc::clone(&check_now),
+    };
+    let checks = async {
+        loop {
+            if let Err(error) = run_checks(&config, &extensions, &notifiers).await {
+                error!("{error:?}");
+            }
+            info!(minutes = server.interval, "waiting for the next check");
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(server.interval * 60)) => {}
+                _ = check_now.notified() => {}
+            }
+        }
+    };
+    tokio::select! {
+        result = run_server(&server, state) => result,
+        _ = checks => Ok(()),
+    }
+}
+
+/// the versions in versions.toml, by name
+pub async fn load_versions() -> Result<HashMap<String, String>> {
+    match tokio::fs::read_to_string(VERSIONS_PATH).await {
+        Ok(v) => toml::from_str(&v).context("failed to deserialize versions.toml"),
+        Err(error) => {
+            warn!(%error, "failed to read versions.toml, versions will be empty");
+            Ok(HashMap::new())
+        }
+    }
+}
+
+/// checks every extension once, and sends the update messages
+async fn run_checks(
+    config: &Arc<Config>,
+    extensions: &[Extension],
+    notifiers: &[RoutedNotifier],
+) -> Result<()> {
+    let versions = load_versions().await?;
+    debug!(?versions);
+    let versions = Arc::new(Mutex::new(versions));
+
+    let uses_prettier = config.formatter == Formatter::Prettier
+        || config.formatters.iter().any(FormatterRule::uses_prettier)
+        || extensions
+            .iter()
+            .any(|e| e.formatters.iter().any(FormatterRule::uses_prettier));
+    let tmp_prettierrc = if !uses_prettier {
+        false
+    } else if !tokio::fs::try_exists(PRETTIERRC_PATH)
         .await
         .context("couldn't check if .prettierrc.json exists")?
     {
@@ -219,8 +350,7 @@ async fn main_error_net() -> Result<()> {
         false
     };
 
-    let checked_extensions =
-        check_extensions(config.force_generate_diffs, extensions, &versions).await;
+    let checked_extensions = check_extensions(config, extensions.to_vec(), &versions).await;
     for (extension, update) in &checked_extensions {
         match update {
             Ok(ref update) => {
@@ -253,17 +383,63 @@ async fn main_error_net() -> Result<()> {
     .await
     .context("failed to write versions.toml")?;
 
-    if let Some(discord_config) = config.discord {
-        send_to_discord(discord_config, &checked_extensions).await;
-    } else {
-        info!("skipping sending update message to discord since there is no discord table in config.toml")
+    let error_events = match config.errors {
+        // without the error state, every error is announced like when [errors] isn't set
+        Some(ref errors) => match track_errors(errors, &checked_extensions).await {
+            Ok(error_events) => error_events,
+            Err(error) => {
+                error!("{error:?}");
+                HashMap::new()
+            }
+        },
+        None => HashMap::new(),
+    };
+
+    if let Some(ref archive) = config.archive {
+        if let Err(error) = archive_versions(archive, &checked_extensions).await {
+            error!("{error:?}");
+        }
+    }
+
+    if config.changelog {
+        if let Err(error) = write_changelogs(&checked_extensions).await {
+            error!("{error:?}");
+        }
+    }
+
+    match record_history(&checked_extensions).await {
+        Ok(history) => {
+            if let Some(ref feed) = config.feed {
+                if let Err(error) = write_feeds(feed, &history.updates).await {
+                    error!("{error:?}");
+                }
+            }
+        }
+        // the feeds are written from the history, so they are left as they were
+        Err(error) => error!("{error:?}"),
+    }
+
+    if notifiers.is_empty() {
+        info!("skipping sending update messages since there are no notifiers or discord table in config.toml")
+    }
+    for notifier in notifiers {
+        let report = Report {
+            checked_extensions: notifier.route.filter(&checked_extensions, &error_events),
+            error_events: &error_events,
+            mention: notifier.escalation_mention.as_deref().or(config
+                .errors
+                .as_ref()
+                .and_then(|errors| errors.mention.as_deref())),
+            template: notifier.template.template(),
+        };
+        notifier.notifier.notify(&report).await;
     }
 
     Ok(())
 }
 
 async fn check_extensions(
-    force_generate_diffs: Option<bool>,
+    config: &Arc<Config>,
     extensions: Vec<Extension>,
     versions: &Arc<Mutex<HashMap<String, String>>>,
 ) -> Vec<(Extension, Result<Option<Update>>)> {
@@ -271,18 +447,12 @@ async fn check_extensions(
 
     let mut tasks = vec![];
     for extension in extensions {
+        let config = Arc::clone(config);
         let versions = Arc::clone(versions);
         tasks.push(tokio::task::spawn(async move {
             let update = match check_extension(&extension).await {
                 Ok((cur_version, crx_url)) => {
-                    get_update(
-                        &force_generate_diffs,
-                        &extension,
-                        versions,
-                        cur_version,
-                        crx_url,
-                    )
-                    .await
+                    get_update(&config, &extension, versions, cur_version, crx_url).await
                 }
                 Err(e) => Err(e),
             };
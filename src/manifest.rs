@@ -0,0 +1,146 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// keys whose values are lists of permissions or match patterns
+const PERMISSION_KEYS: &[&str] = &[
+    "permissions",
+    "optional_permissions",
+    "host_permissions",
+    "optional_host_permissions",
+];
+
+/// values longer than this are shortened in manifest changes
+const MAX_VALUE_LEN: usize = 100;
+
+#[derive(Debug)]
+pub struct Manifest(Value);
+
+#[derive(Debug, Default)]
+pub struct ManifestChanges {
+    /// permissions, host permissions and content script matches, like `permissions: tabs` or `content_scripts: <all_urls>`
+    pub added_permissions: Vec<String>,
+    pub removed_permissions: Vec<String>,
+    /// other top level keys that changed, with their previous and current values as json
+    pub changed_keys: Vec<(String, Option<String>, Option<String>)>,
+}
+
+impl ManifestChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added_permissions.is_empty()
+            && self.removed_permissions.is_empty()
+            && self.changed_keys.is_empty()
+    }
+}
+
+impl Manifest {
+    /// every permission, host permission and content script match, labeled with the key it came from
+    pub fn permissions(&self) -> BTreeSet<String> {
+        let mut permissions = BTreeSet::new();
+        for key in PERMISSION_KEYS {
+            for permission in self
+                .0
+                .get(key)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                permissions.insert(format!("{key}: {}", display_value(permission)));
+            }
+        }
+        for content_script in self
+            .0
+            .get("content_scripts")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            for pattern in content_script
+                .get("matches")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                permissions.insert(format!("content_scripts: {}", display_value(pattern)));
+            }
+        }
+        permissions
+    }
+
+    pub fn changes(&self, prev: &Manifest) -> ManifestChanges {
+        let (permissions, prev_permissions) = (self.permissions(), prev.permissions());
+        let mut changes = ManifestChanges {
+            added_permissions: permissions.difference(&prev_permissions).cloned().collect(),
+            removed_permissions: prev_permissions.difference(&permissions).cloned().collect(),
+            changed_keys: vec![],
+        };
+
+        let empty = serde_json::Map::new();
+        let (cur, prev) = (
+            self.0.as_object().unwrap_or(&empty),
+            prev.0.as_object().unwrap_or(&empty),
+        );
+        let keys = prev
+            .keys()
+            .chain(cur.keys().filter(|k| !prev.contains_key(*k)));
+        for key in keys {
+            // the version always changes and permissions are compared above
+            if key == "version" || PERMISSION_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            let (prev_value, cur_value) = (prev.get(key), cur.get(key));
+            if prev_value != cur_value {
+                changes.changed_keys.push((
+                    key.clone(),
+                    prev_value.map(display_value),
+                    cur_value.map(display_value),
+                ));
+            }
+        }
+        changes
+    }
+}
+
+/// strings are shown without quotes, everything else as compact json
+fn display_value(value: &Value) -> String {
+    let value = match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    };
+    if value.chars().count() > MAX_VALUE_LEN {
+        format!("{}…", value.chars().take(MAX_VALUE_LEN).collect::<String>())
+    } else {
+        value
+    }
+}
+
+#[tracing::instrument]
+pub async fn load_manifest(name: &str, version: &str) -> Result<Manifest> {
+    let manifest = tokio::fs::read(format!("./crx/{name}-{version}/manifest.json"))
+        .await
+        .context("couldn't read manifest.json")?;
+    // some manifests start with a byte order mark
+    let manifest = manifest.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&manifest);
+    Ok(Manifest(
+        serde_json::from_slice(manifest).context("couldn't parse manifest.json")?,
+    ))
+}
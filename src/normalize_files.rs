@@ -0,0 +1,306 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! removes build noise (timestamps, hashes, hashed file names and minified identifiers) from a copy of the formatted
+//! files in `./crx/{name}-{version}-normalized`, so that the normalized diff only shows changes that matter
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::default_true;
+use crate::tokenize_js::{tokenize_js, TokenKind};
+use crate::unpack_bundles::unpacked_dir;
+
+/// replacements that are always applied unless `use_builtin_replacements` is false
+const BUILTIN_REPLACEMENTS: &[(&str, &str)] = &[
+    (
+        r"\b\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:?\d{2})?\b",
+        "[timestamp]",
+    ),
+    (r"\b[0-9a-f]{32,64}\b", "[hash]"),
+];
+
+/// file name segments at least this long that contain a digit are treated as hashes (`main.3f2a9c1b.js`)
+const MIN_HASH_LEN: usize = 8;
+
+/// identifiers this short are treated as minified names in identifiers mode
+const MAX_MINIFIED_LEN: usize = 2;
+
+/// how many bytes at the start of a file are checked for NUL bytes to detect binary files
+const BINARY_CHECK_LEN: usize = 8192;
+
+#[derive(Debug, Deserialize)]
+pub struct NormalizeConfig {
+    #[serde(default = "default_true")]
+    use_builtin_replacements: bool,
+    #[serde(default)]
+    replacements: Vec<Replacement>,
+    #[serde(default = "default_true")]
+    hashed_filenames: bool,
+    #[serde(default)]
+    identifiers: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Replacement {
+    pattern: String,
+    replacement: String,
+}
+
+pub fn normalized_dir(name: &str, version: &str) -> String {
+    format!("./crx/{name}-{version}-normalized")
+}
+
+struct Normalizer {
+    replacements: Vec<(Regex, String)>,
+    hashed_filenames: bool,
+    identifiers: bool,
+}
+
+impl Normalizer {
+    fn new(config: &NormalizeConfig) -> Result<Self> {
+        let mut replacements = vec![];
+        if config.use_builtin_replacements {
+            for (pattern, replacement) in BUILTIN_REPLACEMENTS {
+                replacements.push((Regex::new(pattern).unwrap(), replacement.to_string()));
+            }
+        }
+        for replacement in &config.replacements {
+            replacements.push((
+                Regex::new(&replacement.pattern).with_context(|| {
+                    format!("invalid normalize replacement {}", replacement.pattern)
+                })?,
+                replacement.replacement.clone(),
+            ));
+        }
+        Ok(Self {
+            replacements,
+            hashed_filenames: config.hashed_filenames,
+            identifiers: config.identifiers,
+        })
+    }
+
+    /// returns the normalized files, relative to the normalized directory
+    fn normalize_dir(&self, dir: &Path, prefix: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        let paths = WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect::<Vec<_>>();
+
+        // hashed file names are renamed, and so are references to them
+        let mut renamed = HashMap::new();
+        if self.hashed_filenames {
+            for path in &paths {
+                let name = path.file_name().unwrap().to_string_lossy();
+                let canonical = canonical_filename(&name);
+                if canonical != name {
+                    renamed.insert(name.to_string(), canonical);
+                }
+            }
+        }
+        let references = if renamed.is_empty() {
+            None
+        } else {
+            let mut names = renamed.keys().map(|n| regex::escape(n)).collect::<Vec<_>>();
+            // longest first so that `a.12345678.js.map` isn't matched as `a.12345678.js`
+            names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+            Some(Regex::new(&names.join("|")).unwrap())
+        };
+
+        for path in paths {
+            let contents = match std::fs::read(&path) {
+                Ok(c) => c,
+                Err(error) => {
+                    warn!(%error, path = %path.display(), "failed to read file");
+                    continue;
+                }
+            };
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            let mut normalized_path = prefix.join(relative);
+            if let Some(canonical) = renamed.get(&*path.file_name().unwrap().to_string_lossy()) {
+                normalized_path.set_file_name(canonical);
+            }
+            // if two hashed files end up with the same name, the second one keeps its name
+            if files.contains_key(&normalized_path) {
+                normalized_path = prefix.join(relative);
+            }
+
+            let contents = match String::from_utf8(contents) {
+                Ok(contents)
+                    if !contents.as_bytes()[..contents.len().min(BINARY_CHECK_LEN)]
+                        .contains(&0) =>
+                {
+                    self.normalize(&path, contents, references.as_ref(), &renamed)
+                        .into_bytes()
+                }
+                Ok(contents) => contents.into_bytes(),
+                Err(error) => error.into_bytes(),
+            };
+            files.insert(normalized_path, contents);
+        }
+        files
+    }
+
+    fn normalize(
+        &self,
+        path: &Path,
+        mut contents: String,
+        references: Option<&Regex>,
+        renamed: &HashMap<String, String>,
+    ) -> String {
+        let is_js = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "js" | "mjs" | "cjs"));
+        if self.identifiers && is_js {
+            contents = normalize_identifiers(&contents);
+        }
+        if let Some(references) = references {
+            contents = references
+                .replace_all(&contents, |captures: &regex::Captures| {
+                    renamed[&captures[0]].clone()
+                })
+                .into_owned();
+        }
+        for (pattern, replacement) in &self.replacements {
+            contents = pattern
+                .replace_all(&contents, replacement.as_str())
+                .into_owned();
+        }
+        contents
+    }
+}
+
+/// replaces hash segments in a file name with `[hash]`, for example `main.3f2a9c1b.js` -> `main.[hash].js`
+fn canonical_filename(name: &str) -> String {
+    let (stem, extension) = match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    };
+    let mut canonical = String::with_capacity(name.len());
+    let mut segment_start = 0;
+    for (i, c) in stem.char_indices().chain([(stem.len(), '.')]) {
+        if c != '.' && c != '-' {
+            continue;
+        }
+        let segment = &stem[segment_start..i];
+        // the first segment is never a hash, it's the name of the file
+        let is_hash = segment_start > 0
+            && segment.len() >= MIN_HASH_LEN
+            && segment
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            && segment.bytes().any(|b| b.is_ascii_digit());
+        canonical.push_str(if is_hash { "[hash]" } else { segment });
+        if i < stem.len() {
+            canonical.push(c);
+        }
+        segment_start = i + 1;
+    }
+    canonical.push_str(extension);
+    canonical
+}
+
+/// renames short identifiers to `_1`, `_2`, ... in order of first appearance, so that code that was minified with
+/// different names compares equal. property names are left alone
+fn normalize_identifiers(source: &str) -> String {
+    let tokens = tokenize_js(source);
+    let mut names: HashMap<&str, String> = HashMap::new();
+    let mut normalized = String::with_capacity(source.len());
+    let mut last = 0;
+    let mut prev: Option<&str> = None;
+    for token in &tokens {
+        if token.is_comment() {
+            continue;
+        }
+        let is_property = prev.is_some_and(|p| p == "." || p == "?.");
+        prev = Some(token.text);
+        if token.kind != TokenKind::Word
+            || token.is_keyword()
+            || is_property
+            || token.text.len() > MAX_MINIFIED_LEN
+            || token.text.starts_with('#')
+        {
+            continue;
+        }
+        let next_id = names.len() + 1;
+        let name = names
+            .entry(token.text)
+            .or_insert_with(|| format!("_{next_id}"));
+        normalized.push_str(&source[last..token.start]);
+        normalized.push_str(name);
+        last = token.start + token.text.len();
+    }
+    normalized.push_str(&source[last..]);
+    normalized
+}
+
+/// writes the normalized copy of `./crx/{name}-{version}` (and its unpacked files, if there are any) to
+/// `./crx/{name}-{version}-normalized`, replacing any previously normalized files
+#[tracing::instrument(skip(config))]
+pub async fn normalize_files(config: &NormalizeConfig, name: &str, version: &str) -> Result<()> {
+    let normalizer = Normalizer::new(config)?;
+    let out_dir = PathBuf::from(normalized_dir(name, version));
+    if tokio::fs::try_exists(&out_dir)
+        .await
+        .context("couldn't check if normalized dir exists")?
+    {
+        tokio::fs::remove_dir_all(&out_dir)
+            .await
+            .context("couldn't remove previously normalized dir")?;
+    }
+    tokio::fs::create_dir_all(&out_dir)
+        .await
+        .context("couldn't create normalized dir")?;
+
+    let dir = PathBuf::from(format!("./crx/{name}-{version}"));
+    let unpacked = PathBuf::from(unpacked_dir(name, version));
+    let files = tokio::task::spawn_blocking(move || {
+        let mut files = normalizer.normalize_dir(&dir, Path::new(""));
+        if unpacked.is_dir() {
+            files.extend(normalizer.normalize_dir(&unpacked, Path::new("unpacked")));
+        }
+        files
+    })
+    .await
+    .unwrap();
+    debug!(files = files.len(), "normalized files");
+
+    for (path, contents) in files {
+        let path = out_dir.join(path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("couldn't create {}", parent.display()))?;
+        }
+        tokio::fs::write(&path, contents)
+            .await
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+    }
+    Ok(())
+}
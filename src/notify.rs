@@ -0,0 +1,715 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
+use minijinja::context;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::attachments::Attachment;
+use crate::classify_update::Severity;
+use crate::file_changes::ChangeKind;
+use crate::get_update::Update;
+use crate::post_webhook::{extension_payloads, PostWebhookConfig};
+use crate::render_template::{render_template, TemplateConfig};
+use crate::send_email::EmailConfig;
+use crate::send_push::{GotifyConfig, NtfyConfig};
+use crate::send_to_matrix::MatrixConfig;
+use crate::send_to_slack::SlackConfig;
+use crate::send_to_teams::TeamsConfig;
+use crate::send_to_telegram::TelegramConfig;
+use crate::track_errors::{describe, ErrorEvent};
+use crate::{extensions::Extension, DiscordConfig};
+
+/// the maximum number of changed files that are listed per extension
+const MAX_LISTED_FILES: usize = 15;
+
+/// an extension and the result of checking it for an update
+pub type CheckedExtension = (Extension, Result<Option<Update>>);
+
+/// an extension that was updated from 1.0 to 1.1, with the given diff
+#[cfg(test)]
+pub fn updated_extension(name: &str, diff: Option<&str>) -> CheckedExtension {
+    (
+        Extension {
+            name: name.into(),
+            display_name: name.to_uppercase(),
+            id: "a".repeat(32),
+            url: None,
+            generate_diff: diff.is_some(),
+            formatters: vec![],
+            ignore: None,
+            tags: vec![],
+        },
+        Ok(Some(Update {
+            prev_version: "1.0".into(),
+            cur_version: "1.1".into(),
+            diff: diff.map(Into::into),
+            normalized_diff: None,
+            html_report: None,
+            file_changes: vec![],
+            manifest_changes: None,
+            endpoint_changes: None,
+            secrets: vec![],
+            format_failures: vec![],
+        })),
+    )
+}
+
+/// what a notifier is sent after a run
+pub struct Report<'a> {
+    /// the checked extensions that are routed to the notifier
+    pub checked_extensions: Vec<&'a CheckedExtension>,
+    /// what happened to the error state of extensions, by name. if [errors] isn't set, this is empty and every error
+    /// is announced
+    pub error_events: &'a HashMap<String, ErrorEvent>,
+    /// added to update messages with escalated errors
+    pub mention: Option<&'a str>,
+    /// the notifier's custom template
+    pub template: Option<&'a str>,
+}
+
+impl Report<'_> {
+    /// the error event of an extension, if the extension is or was failing
+    pub fn error_event(&self, extension: &Extension) -> Option<&ErrorEvent> {
+        self.error_events.get(&extension.name)
+    }
+
+    /// renders the notifier's template, or default_template if it doesn't have one or it fails. notes are the notes
+    /// about the attachments, and embeds is set if the updates are sent in discord embeds instead
+    pub fn render(
+        &self,
+        default_template: &str,
+        summary: &Summary,
+        notes: &[String],
+        embeds: bool,
+    ) -> String {
+        let context = context! {
+            title => summary.title(),
+            severity => summary.severity.map(|severity| severity.to_string()),
+            updates => summary.updates,
+            sections => summary
+                .sections
+                .iter()
+                .map(|(title, lines)| context! { title, lines })
+                .collect::<Vec<_>>(),
+            attachments => notes,
+            recovered => summary.recovered,
+            errors => summary.errors,
+            mention => summary.mention,
+            extensions => extension_payloads(self),
+            embeds,
+            footer => footer(),
+            version => env!("CARGO_PKG_VERSION"),
+        };
+        if let Some(template) = self.template {
+            match render_template(template, &context) {
+                Ok(text) => return text,
+                Err(error) => error!("failed to render template, using the default: {error:#}"),
+            }
+        }
+        render_template(default_template, &context).expect("default templates are valid")
+    }
+}
+
+/// sends update messages somewhere
+pub trait Notifier {
+    /// sends the updates and errors. failures are logged instead of returned, so that one notifier failing doesn't
+    /// stop the others
+    async fn notify(&self, report: &Report<'_>);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    Discord(DiscordConfig),
+    Slack(SlackConfig),
+    Matrix(MatrixConfig),
+    Teams(TeamsConfig),
+    Email(EmailConfig),
+    Webhook(PostWebhookConfig),
+    Telegram(TelegramConfig),
+    Ntfy(NtfyConfig),
+    Gotify(GotifyConfig),
+}
+
+/// which of the checked extensions a notifier is sent
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Events {
+    /// updates and errors
+    #[default]
+    All,
+    /// updates and extensions without updates, but not errors
+    Updates,
+    /// only errors and recoveries from errors
+    Errors,
+}
+
+/// the extensions that a notifier is sent. an extension is sent if it is in extensions or has one of tags (or both are
+/// empty), and isn't in exclude_extensions and doesn't have any of exclude_tags
+#[derive(Debug, Default, Deserialize)]
+pub struct Route {
+    /// names of extensions, like "goguardian-alpha"
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    exclude_extensions: Vec<String>,
+    #[serde(default)]
+    exclude_tags: Vec<String>,
+    #[serde(default)]
+    events: Events,
+}
+
+impl Route {
+    fn matches(&self, extension: &Extension) -> bool {
+        let has_tag = |tags: &[String]| extension.tags.iter().any(|tag| tags.contains(tag));
+        let included = (self.extensions.is_empty() && self.tags.is_empty())
+            || self.extensions.contains(&extension.name)
+            || has_tag(&self.tags);
+        included
+            && !self.exclude_extensions.contains(&extension.name)
+            && !has_tag(&self.exclude_tags)
+    }
+
+    pub fn filter<'a>(
+        &self,
+        checked_extensions: &'a [CheckedExtension],
+        error_events: &HashMap<String, ErrorEvent>,
+    ) -> Vec<&'a CheckedExtension> {
+        checked_extensions
+            .iter()
+            .filter(|(extension, update)| {
+                self.matches(extension)
+                    && match self.events {
+                        Events::All => true,
+                        Events::Updates => update.is_ok(),
+                        Events::Errors => {
+                            update.is_err()
+                                || matches!(
+                                    error_events.get(&extension.name),
+                                    Some(ErrorEvent::Recovered(_))
+                                )
+                        }
+                    }
+            })
+            .collect()
+    }
+
+    /// the extension names in the route that aren't being checked, which are probably typos
+    pub fn unknown_extensions<'a>(&'a self, extensions: &[Extension]) -> Vec<&'a str> {
+        self.extensions
+            .iter()
+            .chain(&self.exclude_extensions)
+            .filter(|name| !extensions.iter().any(|e| &&e.name == name))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// a notifier from [[notifiers]] or [discord], with the options that every notifier has
+#[derive(Debug, Deserialize)]
+pub struct RoutedNotifier<T = NotifierConfig> {
+    #[serde(flatten)]
+    pub route: Route,
+    /// overrides the mention in [errors]
+    pub escalation_mention: Option<String>,
+    #[serde(flatten)]
+    pub template: TemplateConfig,
+    #[serde(flatten)]
+    pub notifier: T,
+}
+
+impl<T> RoutedNotifier<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> RoutedNotifier<U> {
+        RoutedNotifier {
+            route: self.route,
+            escalation_mention: self.escalation_mention,
+            template: self.template,
+            notifier: f(self.notifier),
+        }
+    }
+}
+
+impl NotifierConfig {
+    /// checks for options that are required together, which serde can't check
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            NotifierConfig::Discord(config) => {
+                if config.token.is_none() && config.webhooks.is_empty() {
+                    bail!("discord notifiers must have a token or webhooks");
+                }
+            }
+            NotifierConfig::Slack(config) => config.validate()?,
+            NotifierConfig::Email(config) => config.validate()?,
+            NotifierConfig::Matrix(_)
+            | NotifierConfig::Teams(_)
+            | NotifierConfig::Webhook(_)
+            | NotifierConfig::Telegram(_)
+            | NotifierConfig::Ntfy(_)
+            | NotifierConfig::Gotify(_) => {}
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for NotifierConfig {
+    async fn notify(&self, report: &Report<'_>) {
+        match self {
+            NotifierConfig::Discord(config) => config.notify(report).await,
+            NotifierConfig::Slack(config) => config.notify(report).await,
+            NotifierConfig::Matrix(config) => config.notify(report).await,
+            NotifierConfig::Teams(config) => config.notify(report).await,
+            NotifierConfig::Email(config) => config.notify(report).await,
+            NotifierConfig::Webhook(config) => config.notify(report).await,
+            NotifierConfig::Telegram(config) => config.notify(report).await,
+            NotifierConfig::Ntfy(config) => config.notify(report).await,
+            NotifierConfig::Gotify(config) => config.notify(report).await,
+        }
+    }
+}
+
+/// the parts of an update message, before they are formatted for a specific notifier.
+/// lines are markdown list items, which may be indented for nested lists
+pub struct Summary {
+    /// one line per updated extension
+    pub updates: Vec<String>,
+    /// the titles and lines of the other sections, like file changes. empty sections are left out
+    pub sections: Vec<(&'static str, Vec<String>)>,
+    /// one line per extension that couldn't be checked
+    pub errors: Vec<String>,
+    /// one line per extension that was failing and was checked successfully
+    pub recovered: Vec<String>,
+    /// set if an error was escalated
+    pub mention: Option<String>,
+    /// the highest severity of the updates, or None if there are only errors
+    pub severity: Option<Severity>,
+    /// the generated diffs
+    pub attachments: Vec<Attachment>,
+}
+
+/// returns None if there are no updates or errors
+pub fn summarize(report: &Report) -> Option<Summary> {
+    let mut updates_text = vec![];
+    let mut file_changes_text = vec![];
+    let mut endpoints_text = vec![];
+    let mut secrets_text = vec![];
+    let mut format_failures_text = vec![];
+    let mut errors_text = vec![];
+    let mut recovered_text = vec![];
+    let mut mention = None;
+    let mut attachments = vec![];
+    let mut severity = None;
+
+    for (extension, update) in &report.checked_extensions {
+        match update {
+            Ok(ref update) => {
+                if let Some(ref update) = update {
+                    severity = severity.max(Some(update.severity()));
+                    updates_text.push(format!(
+                        "- {}: `{}` -> `{}`{}",
+                        extension.display_name,
+                        update.prev_version,
+                        update.cur_version,
+                        if update.diff.is_some() {
+                            " (diff automatically generated)"
+                        } else {
+                            ""
+                        }
+                    ));
+                    if !update.file_changes.is_empty() {
+                        let count = |kind| {
+                            update
+                                .file_changes
+                                .iter()
+                                .filter(|c| c.kind == kind)
+                                .count()
+                        };
+                        let ignored = update.file_changes.iter().filter(|c| c.ignored).count();
+                        file_changes_text.push(format!(
+                            "- {}: {} added, {} removed, {} modified{}",
+                            extension.display_name,
+                            count(ChangeKind::Added),
+                            count(ChangeKind::Removed),
+                            count(ChangeKind::Modified),
+                            if ignored > 0 {
+                                format!(" ({ignored} ignored)")
+                            } else {
+                                "".into()
+                            }
+                        ));
+                        for change in update.file_changes.iter().take(MAX_LISTED_FILES) {
+                            file_changes_text.push(format!(
+                                "  - {} `{}`{}",
+                                change.kind,
+                                change.path,
+                                if change.ignored { " (ignored)" } else { "" }
+                            ));
+                        }
+                        if update.file_changes.len() > MAX_LISTED_FILES {
+                            file_changes_text.push(format!(
+                                "  - and {} more",
+                                update.file_changes.len() - MAX_LISTED_FILES
+                            ));
+                        }
+                    }
+                    if let Some(ref endpoint_changes) = update.endpoint_changes {
+                        if !endpoint_changes.is_empty() {
+                            endpoints_text.push(format!("- {}:", extension.display_name));
+                            for (kind, endpoint) in endpoint_changes.added.labeled() {
+                                endpoints_text.push(format!("  - added {kind} `{endpoint}`"));
+                            }
+                            for (kind, endpoint) in endpoint_changes.removed.labeled() {
+                                endpoints_text.push(format!("  - removed {kind} `{endpoint}`"));
+                            }
+                        }
+                    }
+                    if !update.secrets.is_empty() {
+                        secrets_text.push(format!("- {}:", extension.display_name));
+                        for secret in &update.secrets {
+                            secrets_text.push(format!(
+                                "  - {} `{}` in `{}:{}`",
+                                secret.kind,
+                                secret.displayed_value(),
+                                secret.file,
+                                secret.line
+                            ));
+                        }
+                    }
+                    if !update.format_failures.is_empty() {
+                        format_failures_text.push(format!("- {}:", extension.display_name));
+                        for failure in &update.format_failures {
+                            format_failures_text.push(format!(
+                                "  - `{}` ({}): {}",
+                                failure.file,
+                                failure.formatter,
+                                failure.error.lines().next().unwrap_or_default()
+                            ));
+                        }
+                    }
+                    for (filename, diff) in update.diff_files(extension) {
+                        attachments.push(Attachment {
+                            filename,
+                            data: diff.as_bytes().to_vec(),
+                        });
+                    }
+                }
+            }
+            Err(error) => {
+                let error_event = report.error_event(extension);
+                if error_event.is_some_and(|event| !event.is_announced()) {
+                    continue;
+                }
+                let mut line = format!("- {}: {error}", extension.display_name).replace(
+                    &std::env::current_dir().unwrap().display().to_string(),
+                    "$PWD",
+                );
+                match error_event {
+                    Some(ErrorEvent::Reminder(state)) => {
+                        line.push_str(&format!(" (still {})", describe(state)))
+                    }
+                    Some(ErrorEvent::Escalated(state)) => {
+                        line.push_str(&format!(" (escalated, {})", describe(state)));
+                        mention = report.mention.map(str::to_string);
+                    }
+                    _ => {}
+                }
+                errors_text.push(line);
+            }
+        }
+        if let Some(ErrorEvent::Recovered(state)) = report.error_event(extension) {
+            recovered_text.push(format!(
+                "- {}: checked successfully after {}",
+                extension.display_name,
+                describe(state)
+            ));
+        }
+    }
+
+    if updates_text.is_empty() && errors_text.is_empty() && recovered_text.is_empty() {
+        return None;
+    }
+    Some(Summary {
+        updates: updates_text,
+        sections: [
+            ("File Changes", file_changes_text),
+            ("Endpoint Changes", endpoints_text),
+            ("New Possible Secrets", secrets_text),
+            ("Formatter Failures", format_failures_text),
+        ]
+        .into_iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .collect(),
+        errors: errors_text,
+        recovered: recovered_text,
+        mention,
+        severity,
+        attachments,
+    })
+}
+
+impl Summary {
+    /// a one line summary, like "Extension updates: 2 updated, 1 errors"
+    pub fn title(&self) -> String {
+        let counts = [
+            (self.updates.len(), "updated"),
+            (self.errors.len(), "errors"),
+            (self.recovered.len(), "recovered"),
+        ]
+        .into_iter()
+        .filter(|&(count, _)| count > 0)
+        .map(|(count, label)| format!("{count} {label}"))
+        .collect::<Vec<_>>();
+        format!("Extension updates: {}", counts.join(", "))
+    }
+}
+
+/// the footer of update messages on platforms other than discord
+pub fn footer() -> String {
+    format!(
+        "Automated by extension-version-watcher {}, open source at https://github.com/staticallyamazing/extension-version-watcher",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// escapes a line of markdown and converts its bold text and code to html
+pub fn inline_html(line: &str) -> String {
+    static INLINE: OnceLock<Regex> = OnceLock::new();
+    let inline = INLINE.get_or_init(|| Regex::new(r"\*\*(.+?)\*\*|`([^`]+)`").unwrap());
+    let line = line
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    inline
+        .replace_all(&line, |captures: &regex::Captures| {
+            match (captures.get(1), captures.get(2)) {
+                (Some(bold), _) => format!("<strong>{}</strong>", bold.as_str()),
+                (_, Some(code)) => format!("<code>{}</code>", code.as_str()),
+                _ => unreachable!(),
+            }
+        })
+        .into_owned()
+}
+
+/// converts the markdown used in update messages (bold text, code and indented lists) to html
+pub fn to_html(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let indent = "&nbsp;".repeat(line.len() - trimmed.len());
+            format!("{indent}{}", inline_html(trimmed))
+        })
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// closes a code block at the end of a chunk
+const CLOSE_FENCE: &str = "\n```";
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
+}
+
+/// splits a message into chunks of at most max_len characters at line boundaries.
+/// lines that are longer than max_len on their own are split wherever they need to be.
+/// a code block that is split is closed at the end of the chunk and opened again at the start of the next one
+pub fn split_message(message: &str, max_len: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+    // the line that opened the code block that the chunk ends in
+    let mut fence: Option<&str> = None;
+    for line in message.split('\n') {
+        let next_fence = match (fence, is_fence(line)) {
+            (Some(_), true) => None,
+            (None, true) => Some(line),
+            (fence, false) => fence,
+        };
+        let reserved = if next_fence.is_some() {
+            CLOSE_FENCE.len()
+        } else {
+            0
+        };
+        let mut rest = line;
+        loop {
+            let separator = usize::from(!chunk.is_empty());
+            let room = max_len.saturating_sub(chunk_len + separator + reserved);
+            let rest_len = rest.chars().count();
+            if rest_len <= room {
+                if separator == 1 {
+                    chunk.push('\n');
+                }
+                chunk.push_str(rest);
+                chunk_len += separator + rest_len;
+                break;
+            }
+            // a line that doesn't fit in an empty chunk is split
+            if chunk.is_empty() || Some(chunk.as_str()) == fence {
+                let split = rest
+                    .char_indices()
+                    .nth(room.max(1))
+                    .map_or(rest.len(), |(i, _)| i);
+                if separator == 1 {
+                    chunk.push('\n');
+                }
+                chunk.push_str(&rest[..split]);
+                rest = &rest[split..];
+            }
+            if fence.is_some() {
+                chunk.push_str(CLOSE_FENCE);
+            }
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = 0;
+            if let Some(fence) = fence {
+                chunk = fence.to_string();
+                chunk_len = fence.chars().count();
+            }
+        }
+        fence = next_fence;
+    }
+    chunks.push(chunk);
+    // messages can't be empty
+    chunks.retain(|chunk| !chunk.trim().is_empty());
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track_errors::ErrorState;
+
+    #[test]
+    fn split_long_line() {
+        assert_eq!(
+            split_message("ab\ncdefghijkl\nm", 4),
+            ["ab", "cdef", "ghij", "kl\nm"]
+        );
+        assert_eq!(split_message("ab\ncd", 5), ["ab\ncd"]);
+        assert_eq!(split_message("ab\ncd", 4), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn split_multi_byte_characters() {
+        // the limit is in characters, not bytes
+        assert_eq!(split_message("éééé\n日本", 4), ["éééé", "日本"]);
+        assert_eq!(split_message("🎉🎉🎉🎉🎉", 2), ["🎉🎉", "🎉🎉", "🎉"]);
+        assert_eq!(split_message("aé\néa", 5), ["aé\néa"]);
+    }
+
+    #[test]
+    fn split_code_blocks() {
+        let message = "intro\n```diff\n+1\n+2\n+3\n+4\n```\nend";
+        let chunks = split_message(message, 20);
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 20, "{chunk:?} is too long");
+            assert_eq!(
+                chunk.lines().filter(|line| is_fence(line)).count() % 2,
+                0,
+                "{chunk:?} has an unclosed code block"
+            );
+        }
+        assert_eq!(
+            chunks,
+            ["intro\n```diff\n+1\n```", "```diff\n+2\n+3\n+4\n```", "end"]
+        );
+
+        // long lines in code blocks are split inside the code block
+        assert_eq!(
+            split_message("```\n0123456789\n```", 10),
+            [
+                "```\n01\n```",
+                "```\n23\n```",
+                "```\n45\n```",
+                "```\n67\n```",
+                "```\n89\n```"
+            ]
+        );
+    }
+
+    /// alpha and beta are tagged "school", beta and gamma "filter", delta failed to check and gamma recovered
+    fn routed_extensions() -> Vec<CheckedExtension> {
+        let tagged = |name: &str, tags: &[&str]| {
+            let mut checked = updated_extension(name, None);
+            checked.0.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            checked
+        };
+        let mut failed = tagged("delta", &["filter"]);
+        failed.1 = Err(anyhow::anyhow!("couldn't download"));
+        vec![
+            tagged("alpha", &["school"]),
+            tagged("beta", &["school", "filter"]),
+            tagged("gamma", &["filter"]),
+            failed,
+        ]
+    }
+
+    #[test]
+    fn route_filter() {
+        let checked = routed_extensions();
+        let state = ErrorState {
+            since: 0,
+            last_announced: 0,
+            failures: 1,
+            escalated: false,
+        };
+        let error_events = HashMap::from([
+            ("gamma".to_string(), ErrorEvent::Recovered(state)),
+            ("delta".to_string(), ErrorEvent::Started(state)),
+        ]);
+        for (route, expected) in [
+            ("", &["alpha", "beta", "gamma", "delta"][..]),
+            (r#"extensions = ["gamma", "unknown"]"#, &["gamma"]),
+            (r#"tags = ["school"]"#, &["alpha", "beta"]),
+            (
+                "extensions = [\"gamma\"]\ntags = [\"school\"]",
+                &["alpha", "beta", "gamma"],
+            ),
+            (
+                r#"exclude_extensions = ["beta"]"#,
+                &["alpha", "gamma", "delta"],
+            ),
+            (r#"exclude_tags = ["school"]"#, &["gamma", "delta"]),
+            (
+                "tags = [\"filter\"]\nexclude_tags = [\"school\"]",
+                &["gamma", "delta"],
+            ),
+            (
+                "extensions = [\"alpha\"]\nexclude_extensions = [\"alpha\"]",
+                &[],
+            ),
+            (r#"events = "all""#, &["alpha", "beta", "gamma", "delta"]),
+            (r#"events = "updates""#, &["alpha", "beta", "gamma"]),
+            (r#"events = "errors""#, &["gamma", "delta"]),
+            ("tags = [\"school\"]\nevents = \"errors\"", &[]),
+        ] {
+            let route: Route = toml::from_str(route).unwrap();
+            let names = route
+                .filter(&checked, &error_events)
+                .iter()
+                .map(|(extension, _)| extension.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, expected, "{route:?}");
+        }
+    }
+}
@@ -0,0 +1,497 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::file_changes::ChangeKind;
+use crate::find_endpoints::Endpoints;
+use crate::get_update::Update;
+use crate::notify::{Notifier, Report};
+use crate::track_errors::ErrorEvent;
+
+/// the version of the payload's schema. it is increased when fields are removed or change meaning, but not when
+/// fields are added. see docs/webhook-payload.md
+const SCHEMA_VERSION: u32 = 1;
+
+/// the header with the hmac-sha256 signature of the body, if a secret is set
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+const fn default_retries() -> u32 {
+    3
+}
+
+const fn default_retry_delay() -> u64 {
+    5
+}
+
+#[derive(Deserialize)]
+pub struct PostWebhookConfig {
+    urls: Vec<String>,
+    /// the key used to sign payloads
+    secret: Option<String>,
+    /// the number of times a failed request is retried
+    #[serde(default = "default_retries")]
+    retries: u32,
+    /// in seconds. doubled after every retry
+    #[serde(default = "default_retry_delay")]
+    retry_delay: u64,
+}
+
+impl Debug for PostWebhookConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the urls may contain tokens
+        f.debug_struct("PostWebhookConfig")
+            .field("urls", &self.urls.len())
+            .field("retries", &self.retries)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    schema_version: u32,
+    generator: Generator,
+    /// unix timestamp, in seconds
+    checked_at: u64,
+    extensions: Vec<ExtensionPayload<'a>>,
+}
+
+#[derive(Serialize)]
+struct Generator {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Unchanged,
+    Updated,
+    Error,
+}
+
+#[derive(Serialize)]
+pub struct ExtensionPayload<'a> {
+    name: &'a str,
+    display_name: &'a str,
+    id: &'a str,
+    status: Status,
+    update: Option<UpdatePayload<'a>>,
+    error: Option<String>,
+    error_state: Option<ErrorStatePayload>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ErrorEventPayload {
+    Started,
+    Ongoing,
+    Reminder,
+    Escalated,
+    Recovered,
+}
+
+#[derive(Serialize)]
+struct ErrorStatePayload {
+    event: ErrorEventPayload,
+    /// unix timestamp, in seconds
+    since: u64,
+    failures: u32,
+}
+
+#[derive(Serialize)]
+pub struct UpdatePayload<'a> {
+    prev_version: &'a str,
+    cur_version: &'a str,
+    version_change: String,
+    severity: String,
+    diff_generated: bool,
+    files: FilesPayload<'a>,
+    manifest: Option<ManifestPayload<'a>>,
+    endpoints: Option<EndpointsPayload<'a>>,
+    secrets: Vec<SecretPayload<'a>>,
+    format_failures: Vec<FormatFailurePayload<'a>>,
+}
+
+#[derive(Serialize)]
+struct FilesPayload<'a> {
+    added: usize,
+    removed: usize,
+    modified: usize,
+    ignored: usize,
+    changes: Vec<FileChangePayload<'a>>,
+}
+
+#[derive(Serialize)]
+struct FileChangePayload<'a> {
+    path: &'a str,
+    kind: String,
+    ignored: bool,
+}
+
+#[derive(Serialize)]
+struct ManifestPayload<'a> {
+    added_permissions: &'a [String],
+    removed_permissions: &'a [String],
+    changed_keys: Vec<ChangedKeyPayload<'a>>,
+}
+
+#[derive(Serialize)]
+struct ChangedKeyPayload<'a> {
+    key: &'a str,
+    prev: Option<&'a str>,
+    cur: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct EndpointsPayload<'a> {
+    added: EndpointListPayload<'a>,
+    removed: EndpointListPayload<'a>,
+}
+
+#[derive(Serialize)]
+struct EndpointListPayload<'a> {
+    hosts: Vec<&'a str>,
+    urls: Vec<&'a str>,
+    websockets: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct SecretPayload<'a> {
+    kind: &'a str,
+    value: &'a str,
+    file: &'a str,
+    line: usize,
+}
+
+#[derive(Serialize)]
+struct FormatFailurePayload<'a> {
+    file: &'a str,
+    formatter: &'a str,
+    error: &'a str,
+}
+
+pub fn update_payload<'a>(update: &'a Update) -> UpdatePayload<'a> {
+    let count = |kind| {
+        update
+            .file_changes
+            .iter()
+            .filter(|c| c.kind == kind)
+            .count()
+    };
+    let endpoint_list = |endpoints: &'a Endpoints| EndpointListPayload {
+        hosts: endpoints.hosts.iter().map(String::as_str).collect(),
+        urls: endpoints.urls.iter().map(String::as_str).collect(),
+        websockets: endpoints.websockets.iter().map(String::as_str).collect(),
+    };
+    UpdatePayload {
+        prev_version: &update.prev_version,
+        cur_version: &update.cur_version,
+        version_change: update.version_change().to_string(),
+        severity: update.severity().to_string(),
+        diff_generated: update.diff.is_some(),
+        files: FilesPayload {
+            added: count(ChangeKind::Added),
+            removed: count(ChangeKind::Removed),
+            modified: count(ChangeKind::Modified),
+            ignored: update.file_changes.iter().filter(|c| c.ignored).count(),
+            changes: update
+                .file_changes
+                .iter()
+                .map(|change| FileChangePayload {
+                    path: &change.path,
+                    kind: change.kind.to_string(),
+                    ignored: change.ignored,
+                })
+                .collect(),
+        },
+        manifest: update
+            .manifest_changes
+            .as_ref()
+            .map(|changes| ManifestPayload {
+                added_permissions: &changes.added_permissions,
+                removed_permissions: &changes.removed_permissions,
+                changed_keys: changes
+                    .changed_keys
+                    .iter()
+                    .map(|(key, prev, cur)| ChangedKeyPayload {
+                        key,
+                        prev: prev.as_deref(),
+                        cur: cur.as_deref(),
+                    })
+                    .collect(),
+            }),
+        endpoints: update
+            .endpoint_changes
+            .as_ref()
+            .map(|changes| EndpointsPayload {
+                added: endpoint_list(&changes.added),
+                removed: endpoint_list(&changes.removed),
+            }),
+        secrets: update
+            .secrets
+            .iter()
+            .map(|secret| SecretPayload {
+                kind: &secret.kind,
+                value: &secret.value,
+                file: &secret.file,
+                line: secret.line,
+            })
+            .collect(),
+        format_failures: update
+            .format_failures
+            .iter()
+            .map(|failure| FormatFailurePayload {
+                file: &failure.file,
+                formatter: &failure.formatter,
+                error: &failure.error,
+            })
+            .collect(),
+    }
+}
+
+fn error_state_payload(event: &ErrorEvent) -> ErrorStatePayload {
+    let (event, state) = match event {
+        ErrorEvent::Started(state) => (ErrorEventPayload::Started, state),
+        ErrorEvent::Ongoing(state) => (ErrorEventPayload::Ongoing, state),
+        ErrorEvent::Reminder(state) => (ErrorEventPayload::Reminder, state),
+        ErrorEvent::Escalated(state) => (ErrorEventPayload::Escalated, state),
+        ErrorEvent::Recovered(state) => (ErrorEventPayload::Recovered, state),
+    };
+    ErrorStatePayload {
+        event,
+        since: state.since,
+        failures: state.failures,
+    }
+}
+
+/// the extensions of the payload, which are also given to templates
+pub fn extension_payloads<'a>(report: &Report<'a>) -> Vec<ExtensionPayload<'a>> {
+    report
+        .checked_extensions
+        .iter()
+        .map(|(extension, update)| {
+            let (status, update, error) = match update {
+                Ok(Some(update)) => (Status::Updated, Some(update_payload(update)), None),
+                Ok(None) => (Status::Unchanged, None, None),
+                Err(error) => (
+                    Status::Error,
+                    None,
+                    Some(format!("{error:#}").replace(
+                        &std::env::current_dir().unwrap().display().to_string(),
+                        "$PWD",
+                    )),
+                ),
+            };
+            ExtensionPayload {
+                name: &extension.name,
+                display_name: &extension.display_name,
+                id: &extension.id,
+                status,
+                update,
+                error,
+                error_state: report.error_event(extension).map(error_state_payload),
+            }
+        })
+        .collect()
+}
+
+fn payload<'a>(report: &Report<'a>) -> Payload<'a> {
+    Payload {
+        schema_version: SCHEMA_VERSION,
+        generator: Generator {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+        },
+        checked_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        extensions: extension_payloads(report),
+    }
+}
+
+/// `sha256=` followed by the hex encoded hmac-sha256 of the body
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+impl Notifier for PostWebhookConfig {
+    #[tracing::instrument(name = "post_webhook", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let body = serde_json::to_vec(&payload(report)).unwrap();
+        let signature = self.secret.as_ref().map(|secret| sign(secret, &body));
+        let client = Client::new();
+        for (i, url) in self.urls.iter().enumerate() {
+            async {
+                match self.post(&client, url, &body, signature.as_deref()).await {
+                    Ok(_) => info!("posted payload"),
+                    Err(error) => error!("failed to post payload: {error:#}"),
+                }
+            }
+            .instrument(tracing::info_span!("url", i))
+            .await
+        }
+    }
+}
+
+impl PostWebhookConfig {
+    async fn post(
+        &self,
+        client: &Client,
+        url: &str,
+        body: &[u8],
+        signature: Option<&str>,
+    ) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            let mut request = client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body.to_vec());
+            if let Some(signature) = signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            debug!(attempt, "posting payload");
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    let error = anyhow::anyhow!("responded with {status}: {body}");
+                    // other client errors won't be fixed by retrying
+                    if status.is_client_error() && status.as_u16() != 429 {
+                        return Err(error);
+                    }
+                    error
+                }
+                // the url may contain tokens
+                Err(error) => anyhow::Error::new(error.without_url()).context("couldn't post"),
+            };
+            if attempt >= self.retries {
+                bail!("{error:#} (after {attempt} retries)");
+            }
+            let delay = Duration::from_secs(self.retry_delay << attempt.min(10));
+            warn!(?delay, "failed to post payload, retrying: {error:#}");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::notify::updated_extension;
+    use crate::test_server::serve_statuses;
+
+    fn config(url: &str, secret: Option<&str>) -> PostWebhookConfig {
+        PostWebhookConfig {
+            urls: vec![url.to_string()],
+            secret: secret.map(str::to_string),
+            retries: 2,
+            retry_delay: 0,
+        }
+    }
+
+    #[test]
+    fn signature() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_signed_payload() {
+        let (url, mut requests) = serve_statuses(&[200], "").await;
+        let checked = updated_extension("foo", None);
+        config(&url, Some("secret"))
+            .notify(&Report {
+                checked_extensions: vec![&checked],
+                error_events: &HashMap::new(),
+                mention: None,
+                template: None,
+            })
+            .await;
+
+        let request = requests.try_recv().unwrap();
+        assert!(requests.try_recv().is_err());
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(
+            request.header("x-signature-256"),
+            Some(sign("secret", &request.body).as_str())
+        );
+        let payload = request.json();
+        assert_eq!(payload["schema_version"], SCHEMA_VERSION);
+        assert_eq!(payload["extensions"][0]["name"], "foo");
+        assert_eq!(payload["extensions"][0]["status"], "updated");
+        assert_eq!(payload["extensions"][0]["update"]["cur_version"], "1.1");
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, mut requests) = serve_statuses(&[500, 429, 200], "").await;
+        let client = Client::new();
+        config(&url, None)
+            .post(&client, &url, b"{}", None)
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            let request = requests.try_recv().unwrap();
+            assert_eq!(request.header("x-signature-256"), None);
+        }
+        assert!(requests.try_recv().is_err());
+
+        let (url, mut requests) = serve_statuses(&[503], "").await;
+        let error = config(&url, None)
+            .post(&client, &url, b"{}", None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("after 2 retries"));
+        // the first attempt and 2 retries
+        for _ in 0..3 {
+            requests.try_recv().unwrap();
+        }
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn doesnt_retry_client_errors() {
+        let (url, mut requests) = serve_statuses(&[400, 200], "bad payload").await;
+        let error = config(&url, None)
+            .post(&Client::new(), &url, b"{}", None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("400 Bad Request: bad payload"));
+        requests.try_recv().unwrap();
+        assert!(requests.try_recv().is_err());
+    }
+}
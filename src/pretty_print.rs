@@ -0,0 +1,871 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! the builtin formatter. the output is meant to make diffs readable, so it is deterministic and
+//! puts one statement / declaration / tag per line, but it does not try to look like any particular code style.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::tokenize_js::{tokenize_js, Token, TokenKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Js,
+    Css,
+    Html,
+    Json,
+}
+
+impl Language {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "js" | "mjs" | "cjs" => Some(Self::Js),
+            "css" => Some(Self::Css),
+            "html" | "htm" => Some(Self::Html),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+pub fn pretty_print(language: Language, source: &str, indent: usize) -> Result<String> {
+    Ok(match language {
+        Language::Js => pretty_print_js(source, indent),
+        Language::Css => pretty_print_css(source, indent),
+        Language::Html => pretty_print_html(source, indent),
+        Language::Json => pretty_print_json(source, indent)?,
+    })
+}
+
+/// formats a file in place. files that aren't javascript, css, html or json are left alone
+pub fn pretty_print_file(path: &Path, indent: usize) -> Result<()> {
+    let Some(language) = Language::from_path(path) else {
+        return Ok(());
+    };
+    let source = std::fs::read(path).context("couldn't read file")?;
+    let source = String::from_utf8(source).context("file is not valid UTF-8")?;
+    let formatted = pretty_print(language, &source, indent)?;
+    if formatted != source {
+        std::fs::write(path, formatted).context("couldn't write formatted file")?;
+    }
+    Ok(())
+}
+
+struct Writer {
+    out: String,
+    indent: String,
+    /// added to level, for code that is embedded in another file
+    base_level: usize,
+    level: usize,
+    at_line_start: bool,
+}
+
+impl Writer {
+    fn new(indent: usize) -> Self {
+        Self {
+            out: String::new(),
+            indent: " ".repeat(indent),
+            base_level: 0,
+            level: 0,
+            at_line_start: true,
+        }
+    }
+
+    fn newline(&mut self) {
+        let trimmed_len = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed_len);
+        if !self.at_line_start {
+            self.out.push('\n');
+            self.at_line_start = true;
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.at_line_start {
+            for _ in 0..self.base_level + self.level {
+                self.out.push_str(&self.indent);
+            }
+        }
+        self.out.push_str(text);
+        self.at_line_start = false;
+    }
+
+    fn space(&mut self) {
+        if !self.at_line_start && !self.out.ends_with(' ') {
+            self.out.push(' ');
+        }
+    }
+
+    /// writes text that may contain multiple lines, indenting every line
+    fn write_lines(&mut self, text: &str) {
+        for line in text.lines() {
+            self.write(line);
+            self.newline();
+        }
+    }
+
+    /// writes text that is already indented on its own lines
+    fn write_indented(&mut self, text: &str) {
+        self.newline();
+        self.out.push_str(text);
+        self.at_line_start = text.is_empty() || text.ends_with('\n');
+    }
+
+    fn finish(mut self) -> String {
+        self.newline();
+        self.out
+    }
+}
+
+struct Bracket {
+    kind: char,
+    /// `?` that haven't been matched by a `:` yet
+    ternaries: usize,
+    /// if a `case` or `default` is waiting for its `:`
+    pending_case: bool,
+    /// if the statements of a `case` are being written, which are indented one more level
+    in_case: bool,
+    /// if a `var`, `let` or `const` statement is being written, which indents the declarations after the first one
+    in_declaration: bool,
+    /// if this is the body of a do-while loop
+    after_do: bool,
+}
+
+impl Bracket {
+    fn new(kind: char) -> Self {
+        Self {
+            kind,
+            ternaries: 0,
+            pending_case: false,
+            in_case: false,
+            in_declaration: false,
+            after_do: false,
+        }
+    }
+}
+
+fn indent_level(stack: &[Bracket]) -> usize {
+    stack
+        .iter()
+        .map(|b| (b.kind == '{') as usize + b.in_case as usize + b.in_declaration as usize)
+        .sum::<usize>()
+        - 1
+}
+
+pub fn pretty_print_js(source: &str, indent: usize) -> String {
+    pretty_print_embedded_js(source, indent, 0)
+}
+
+/// formats javascript that is indented by base_level in the file it's in. the text of template literals and block
+/// comments is left as it is, since indenting it would change it
+fn pretty_print_embedded_js(source: &str, indent: usize, base_level: usize) -> String {
+    let tokens = tokenize_js(source);
+    let mut w = Writer::new(indent);
+    w.base_level = base_level;
+    // the bottom bracket is the top level of the file and is never popped
+    let mut stack = vec![Bracket::new('{')];
+    let mut prev: Option<Token> = None;
+    let mut prev_unary = false;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let t = tokens[i];
+        let next = tokens.get(i + 1);
+        i += 1;
+
+        match t.kind {
+            TokenKind::LineComment => {
+                if t.newline_before {
+                    w.newline();
+                }
+                w.space();
+                w.write(t.text);
+                w.newline();
+                continue;
+            }
+            TokenKind::BlockComment => {
+                if t.newline_before {
+                    w.newline();
+                }
+                w.space();
+                w.write(t.text);
+                if next.is_some_and(|n| n.newline_before) {
+                    w.newline();
+                } else {
+                    w.space();
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let top = stack.last_mut().unwrap();
+        let at_statement_level = top.kind == '{';
+        let prev_ends_value = prev.is_some_and(|p| {
+            p.ends_value()
+                || (p.kind == TokenKind::Punct && matches!(p.text, "++" | "--") && !prev_unary)
+        });
+
+        // keep line breaks in code that relies on automatic semicolon insertion
+        if t.newline_before && at_statement_level && prev_ends_value && starts_statement(&t) {
+            top.in_declaration = false;
+            w.newline();
+        }
+        if t.is("else") && !prev.is_some_and(|p| p.is("}")) {
+            w.newline();
+        }
+        if at_statement_level && (t.is("case") || t.is("default")) && top.in_case {
+            top.in_case = false;
+            w.newline();
+        }
+        w.level = indent_level(&stack);
+        let top = stack.last_mut().unwrap();
+
+        if needs_space(prev.as_ref(), prev_unary, prev_ends_value, &t, top) {
+            w.space();
+        }
+
+        let unary = t.kind == TokenKind::Punct
+            && match t.text {
+                "!" | "~" => true,
+                "+" | "-" | "++" | "--" => !prev_ends_value,
+                _ => false,
+            };
+
+        if t.kind != TokenKind::Punct {
+            w.write(t.text);
+            if at_statement_level {
+                match t.text {
+                    "case" | "default" => top.pending_case = true,
+                    "var" | "let" | "const" => top.in_declaration = true,
+                    _ => {}
+                }
+            }
+            prev = Some(t);
+            prev_unary = false;
+            continue;
+        }
+
+        match t.text {
+            "{" => {
+                w.write("{");
+                if next.is_some_and(|n| n.is("}")) {
+                    w.write("}");
+                    i += 1;
+                    close_block(&mut w, tokens.get(i), false);
+                    prev = Some(tokens[i - 1]);
+                    prev_unary = false;
+                    continue;
+                }
+                let mut bracket = Bracket::new('{');
+                bracket.after_do = prev.is_some_and(|p| p.is("do"));
+                stack.push(bracket);
+                w.newline();
+            }
+            "}" => {
+                let mut after_do = false;
+                while stack.len() > 1 {
+                    let bracket = stack.pop().unwrap();
+                    if bracket.kind == '{' {
+                        after_do = bracket.after_do;
+                        break;
+                    }
+                }
+                w.level = indent_level(&stack);
+                w.newline();
+                w.write("}");
+                close_block(&mut w, next, after_do);
+            }
+            "(" => {
+                w.write("(");
+                stack.push(Bracket::new('('));
+            }
+            "[" => {
+                w.write("[");
+                stack.push(Bracket::new('['));
+            }
+            ")" | "]" => {
+                let opener = if t.is(")") { '(' } else { '[' };
+                if stack.len() > 1 && stack.iter().any(|b| b.kind == opener) {
+                    while stack.len() > 1 && stack.pop().unwrap().kind != opener {}
+                }
+                w.write(t.text);
+            }
+            ";" => {
+                w.write(";");
+                if top.kind != '(' {
+                    top.pending_case = false;
+                    top.in_declaration = false;
+                    w.newline();
+                }
+            }
+            "," => {
+                w.write(",");
+                if at_statement_level {
+                    w.newline();
+                }
+            }
+            "?" => {
+                top.ternaries += 1;
+                w.write("?");
+            }
+            ":" => {
+                w.write(":");
+                if top.ternaries > 0 {
+                    top.ternaries -= 1;
+                } else if top.pending_case {
+                    top.pending_case = false;
+                    top.in_case = true;
+                    w.newline();
+                }
+            }
+            _ => w.write(t.text),
+        }
+        prev = Some(t);
+        prev_unary = unary;
+    }
+
+    w.finish()
+}
+
+fn starts_statement(t: &Token) -> bool {
+    match t.kind {
+        TokenKind::Word => !matches!(
+            t.text,
+            "in" | "instanceof" | "of" | "else" | "catch" | "finally"
+        ),
+        TokenKind::Number | TokenKind::String | TokenKind::Template => true,
+        _ => false,
+    }
+}
+
+fn needs_space(
+    prev: Option<&Token>,
+    prev_unary: bool,
+    prev_ends_value: bool,
+    cur: &Token,
+    top: &Bracket,
+) -> bool {
+    let Some(prev) = prev else {
+        return false;
+    };
+    if cur.kind == TokenKind::Punct {
+        match cur.text {
+            ")" | "]" | "," | ";" | "." | "?." => return false,
+            ":" => return top.ternaries > 0,
+            _ => {}
+        }
+    }
+    // `- -a` must not turn into `--a`
+    if prev_unary
+        && cur.kind == TokenKind::Punct
+        && prev.text.ends_with(['+', '-'])
+        && cur.text.starts_with(prev.text.chars().last().unwrap())
+    {
+        return true;
+    }
+    if prev_unary
+        || (prev.kind == TokenKind::Punct
+            && matches!(prev.text, "(" | "[" | "." | "?." | "..." | "!" | "~"))
+    {
+        return false;
+    }
+    if cur.kind == TokenKind::Punct {
+        match cur.text {
+            "(" | "[" => {
+                return !prev_ends_value && !matches!(prev.text, "function" | "import");
+            }
+            "++" | "--" => return !prev_ends_value,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// decides if a line break should follow a `}`
+fn close_block(w: &mut Writer, next: Option<&Token>, after_do: bool) {
+    let continues = match next {
+        None => false,
+        Some(n) => match n.kind {
+            TokenKind::Punct => !matches!(n.text, "{" | "!" | "~"),
+            TokenKind::Word => {
+                matches!(
+                    n.text,
+                    "else" | "catch" | "finally" | "in" | "instanceof" | "of"
+                ) || (n.is("while") && after_do)
+            }
+            TokenKind::LineComment | TokenKind::BlockComment => true,
+            _ => false,
+        },
+    };
+    if !continues {
+        w.newline();
+    }
+}
+
+pub fn pretty_print_css(source: &str, indent: usize) -> String {
+    let mut w = Writer::new(indent);
+    let mut buffer = String::new();
+    let mut paren_depth = 0usize;
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' if source[i..].starts_with("/*") => {
+                let end = source[i + 2..]
+                    .find("*/")
+                    .map(|e| i + 2 + e + 2)
+                    .unwrap_or(source.len());
+                if buffer.trim().is_empty() {
+                    w.newline();
+                    w.write(&source[i..end]);
+                    w.newline();
+                } else {
+                    buffer.push_str(&source[i..end]);
+                }
+                while chars.peek().is_some_and(|(j, _)| *j < end) {
+                    chars.next();
+                }
+            }
+            '"' | '\'' => {
+                buffer.push(c);
+                let mut escaped = false;
+                for (_, s) in chars.by_ref() {
+                    buffer.push(s);
+                    if escaped {
+                        escaped = false;
+                    } else if s == '\\' {
+                        escaped = true;
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                paren_depth += 1;
+                buffer.push(c);
+            }
+            ')' => {
+                paren_depth = paren_depth.saturating_sub(1);
+                buffer.push(c);
+            }
+            '{' if paren_depth == 0 => {
+                let selector = buffer.trim();
+                let parts = split_top_level(selector, ',');
+                for (n, part) in parts.iter().enumerate() {
+                    if n > 0 {
+                        w.write(",");
+                        w.newline();
+                    }
+                    w.write(part.trim());
+                }
+                w.space();
+                w.write("{");
+                w.level += 1;
+                w.newline();
+                buffer.clear();
+            }
+            ';' if paren_depth == 0 => {
+                write_declaration(&mut w, &buffer);
+                buffer.clear();
+            }
+            '}' if paren_depth == 0 => {
+                write_declaration(&mut w, &buffer);
+                buffer.clear();
+                w.level = w.level.saturating_sub(1);
+                w.newline();
+                w.write("}");
+                w.newline();
+            }
+            c if c.is_whitespace() => {
+                if !buffer.is_empty() && !buffer.ends_with(' ') {
+                    buffer.push(' ');
+                }
+            }
+            c => buffer.push(c),
+        }
+    }
+    if !buffer.trim().is_empty() {
+        w.write(buffer.trim());
+    }
+
+    w.finish()
+}
+
+fn write_declaration(w: &mut Writer, declaration: &str) {
+    let declaration = declaration.trim();
+    if declaration.is_empty() {
+        return;
+    }
+    match declaration.split_once(':') {
+        Some((property, value)) if !declaration.starts_with('@') => {
+            w.write(property.trim());
+            w.write(": ");
+            w.write(value.trim());
+        }
+        _ => w.write(declaration),
+    }
+    w.write(";");
+    w.newline();
+}
+
+/// splits text at a separator that is not inside parentheses, brackets or quotes
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth = depth.saturating_sub(1),
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// elements whose contents are not html
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "pre"];
+
+pub fn pretty_print_html(source: &str, indent: usize) -> String {
+    let mut w = Writer::new(indent);
+    let mut stack: Vec<String> = vec![];
+    let lowercase = source.to_ascii_lowercase();
+    let mut i = 0;
+
+    while i < source.len() {
+        let rest = &source[i..];
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map(|e| e + 3).unwrap_or(rest.len());
+            w.newline();
+            w.write(&rest[..end]);
+            w.newline();
+            i += end;
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map(|e| e + 1).unwrap_or(rest.len());
+            w.newline();
+            w.write(&collapse_whitespace(&rest[..end]));
+            w.newline();
+            i += end;
+        } else if rest.starts_with("</") && rest[2..].starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            let end = rest.find('>').map(|e| e + 1).unwrap_or(rest.len());
+            let name = tag_name(&rest[2..end]);
+            if let Some(pos) = stack.iter().rposition(|n| *n == name) {
+                stack.truncate(pos);
+                w.level = stack.len();
+            }
+            w.newline();
+            w.write(&format!("</{name}>"));
+            w.newline();
+            i += end;
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            let end = find_tag_end(rest);
+            let tag = &rest[..end];
+            let name = tag_name(&tag[1..]);
+            let self_closing = tag.ends_with("/>");
+            w.newline();
+            w.write(&normalize_tag(tag));
+            w.newline();
+            i += end;
+
+            if self_closing || VOID_ELEMENTS.contains(&name.as_str()) {
+                continue;
+            }
+            if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                let content_end = lowercase[i..]
+                    .find(&format!("</{name}"))
+                    .map(|e| i + e)
+                    .unwrap_or(source.len());
+                let content = &source[i..content_end];
+                w.level = stack.len() + 1;
+                match name.as_str() {
+                    "script" if is_javascript(tag) => w.write_indented(&pretty_print_embedded_js(
+                        content,
+                        indent,
+                        stack.len() + 1,
+                    )),
+                    "style" => w.write_lines(&pretty_print_css(content, indent)),
+                    _ if !content.trim().is_empty() => {
+                        w.write(content.trim_matches(['\n', '\r']));
+                        w.newline();
+                    }
+                    _ => {}
+                }
+                w.level = stack.len();
+                i = content_end;
+            } else {
+                stack.push(name);
+                w.level = stack.len();
+            }
+        } else {
+            let first_len = rest.chars().next().map_or(0, char::len_utf8);
+            let end = rest[first_len..]
+                .find('<')
+                .map(|e| e + first_len)
+                .unwrap_or(rest.len());
+            let text = collapse_whitespace(&rest[..end]);
+            if !text.trim().is_empty() {
+                w.newline();
+                w.write(text.trim());
+                w.newline();
+            }
+            i += end;
+        }
+    }
+
+    w.finish()
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// finds the end of a tag, skipping `>` in quoted attribute values
+fn find_tag_end(tag: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    tag.len()
+}
+
+/// collapses whitespace outside of quoted attribute values
+fn normalize_tag(tag: &str) -> String {
+    let mut normalized = String::with_capacity(tag.len());
+    let mut quote = None;
+    for c in tag.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                normalized.push(c);
+            }
+            (Some(_), c) => normalized.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                normalized.push(c);
+            }
+            (None, c) if c.is_whitespace() => {
+                if !normalized.ends_with(' ') {
+                    normalized.push(' ');
+                }
+            }
+            (None, '>') => {
+                let trimmed_len = normalized.trim_end().len();
+                normalized.truncate(trimmed_len);
+                if normalized.ends_with('/') {
+                    normalized.pop();
+                    let trimmed_len = normalized.trim_end().len();
+                    normalized.truncate(trimmed_len);
+                    normalized.push_str(" /");
+                }
+                normalized.push('>');
+            }
+            (None, c) => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+fn is_javascript(script_tag: &str) -> bool {
+    let tag = script_tag.to_ascii_lowercase();
+    match tag.find("type=") {
+        None => true,
+        Some(pos) => {
+            let value = &tag[pos + 5..];
+            value.contains("javascript") || value.contains("module")
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !collapsed.ends_with(' ') {
+                collapsed.push(' ');
+            }
+        } else {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
+/// re-indents JSON without parsing it into a value, so that keys stay in their original order and numbers keep
+/// their original text
+pub fn pretty_print_json(source: &str, indent: usize) -> Result<String> {
+    let source = source.trim_start_matches('\u{feff}');
+    serde_json::from_str::<serde::de::IgnoredAny>(source).context("invalid JSON")?;
+
+    let indent = " ".repeat(indent);
+    let mut out = String::with_capacity(source.len());
+    let mut level = 0;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                out.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if let Some(&close @ ('}' | ']')) = chars.peek() {
+                    out.push(close);
+                    chars.next();
+                } else {
+                    level += 1;
+                    out.push('\n');
+                    out.push_str(&indent.repeat(level));
+                }
+            }
+            '}' | ']' => {
+                level -= 1;
+                out.push('\n');
+                out.push_str(&indent.repeat(level));
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                out.push('\n');
+                out.push_str(&indent.repeat(level));
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_non_ascii_text() {
+        assert_eq!(
+            pretty_print_html("<p>été <b>x</b> ü</p>", 2),
+            "<p>\n  été\n  <b>\n    x\n  </b>\n  ü\n</p>\n"
+        );
+        assert_eq!(pretty_print_html("日本語", 2), "日本語\n");
+    }
+
+    #[test]
+    fn html_comments_and_scripts() {
+        assert_eq!(
+            pretty_print_html("<div><!-- a <b> --><script>if(a){b()}</script></div>", 2),
+            "<div>\n  <!-- a <b> -->\n  <script>\n    if (a) {\n      b()\n    }\n  </script>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn js_comments() {
+        assert_eq!(
+            pretty_print_js("a();// x\n/* y */b()", 2),
+            "a();\n// x\n/* y */ b()\n"
+        );
+    }
+
+    #[test]
+    fn js_regex_and_division() {
+        assert_eq!(
+            pretty_print_js("x=a/b/c;y=/[/]+/g.test(z)", 2),
+            "x = a / b / c;\ny = /[/]+/g.test(z)\n"
+        );
+    }
+
+    #[test]
+    fn js_template_literals() {
+        assert_eq!(
+            pretty_print_js("f(`a ${b}\n  c`);g()", 2),
+            "f(`a ${b}\n  c`);\ng()\n"
+        );
+        assert_eq!(
+            pretty_print_js("if(a){f(`x\ny`)}", 2),
+            "if (a) {\n  f(`x\ny`)\n}\n"
+        );
+    }
+
+    #[test]
+    fn html_script_template_literals() {
+        assert_eq!(
+            pretty_print_html("<script>f(`a\n  b\nc`)</script>", 2),
+            "<script>\n  f(`a\n  b\nc`)\n</script>\n"
+        );
+    }
+
+    #[test]
+    fn js_non_ascii() {
+        assert_eq!(
+            pretty_print_js("const été=\"ça\";f(été)", 2),
+            "const été = \"ça\";\nf(été)\n"
+        );
+    }
+
+    #[test]
+    fn json_keeps_order_and_numbers() {
+        assert_eq!(
+            pretty_print_json(
+                "\u{feff}{\"b\":1.50,\"a\":[],\"c\":{ },\"d\":[1e400,\"x\\\"{,\"],\"é\":12345678901234567890}",
+                2
+            )
+            .unwrap(),
+            "{\n  \"b\": 1.50,\n  \"a\": [],\n  \"c\": {},\n  \"d\": [\n    1e400,\n    \"x\\\"{,\"\n  ],\n  \"é\": 12345678901234567890\n}\n"
+        );
+        assert!(pretty_print_json("{\"a\":", 2).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::file_changes::ChangeKind;
+use crate::notify::CheckedExtension;
+
+const HISTORY_PATH: &str = "./history.toml";
+const HISTORY_HEADER: &str = r#"# history file for extension-version-watcher
+# this file is used to keep a record of every update and the last check of each extension, which feeds and the dashboard are generated from.
+# you should not modify this file. if you delete it, the history starts over
+
+"#;
+
+/// an update that was found by a previous run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub name: String,
+    pub display_name: String,
+    pub prev_version: String,
+    pub cur_version: String,
+    /// unix timestamp, in seconds
+    pub checked_at: u64,
+    pub version_change: String,
+    pub severity: String,
+    pub files_added: usize,
+    pub files_removed: usize,
+    pub files_modified: usize,
+    #[serde(default)]
+    pub added_permissions: Vec<String>,
+    #[serde(default)]
+    pub removed_permissions: Vec<String>,
+    /// other top level manifest keys that changed
+    #[serde(default)]
+    pub changed_keys: Vec<String>,
+    pub added_endpoints: usize,
+    pub removed_endpoints: usize,
+    /// the kinds of the possible secrets that were found. the values aren't kept
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// the names of the files in ./diff
+    #[serde(default)]
+    pub diffs: Vec<String>,
+}
+
+/// an update of an extension from 1.0 to 1.1, with a diff
+#[cfg(test)]
+pub fn history_entry(name: &str, checked_at: u64) -> HistoryEntry {
+    HistoryEntry {
+        name: name.into(),
+        display_name: name.to_uppercase(),
+        prev_version: "1.0".into(),
+        cur_version: "1.1".into(),
+        checked_at,
+        version_change: "minor".into(),
+        severity: "low".into(),
+        files_added: 1,
+        files_removed: 0,
+        files_modified: 2,
+        added_permissions: vec!["tabs".into()],
+        removed_permissions: vec![],
+        changed_keys: vec![],
+        added_endpoints: 0,
+        removed_endpoints: 0,
+        secrets: vec![],
+        diffs: vec![format!("{name}-1.0-1.1.diff")],
+    }
+}
+
+/// the result of the last check of an extension
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastCheck {
+    /// unix timestamp, in seconds
+    pub checked_at: u64,
+    /// the error, if the check failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    /// oldest first
+    #[serde(default)]
+    pub updates: Vec<HistoryEntry>,
+    /// by name
+    #[serde(default)]
+    pub checks: BTreeMap<String, LastCheck>,
+}
+
+/// like "2023-09-14T18:03:00Z"
+pub fn rfc3339(secs: u64) -> String {
+    // days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let time = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+pub async fn load_history() -> Result<History> {
+    match tokio::fs::read_to_string(HISTORY_PATH).await {
+        Ok(history) => toml::from_str(&history).context("failed to deserialize history.toml"),
+        Err(error) => {
+            debug!(%error, "failed to read history.toml, the history will be empty");
+            Ok(History::default())
+        }
+    }
+}
+
+/// adds the updates and checks of this run to history.toml, and returns the updated history
+#[tracing::instrument(skip_all)]
+pub async fn record_history(checked_extensions: &[CheckedExtension]) -> Result<History> {
+    let mut history = load_history().await?;
+
+    let checked_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for (extension, update) in checked_extensions {
+        history.checks.insert(
+            extension.name.clone(),
+            LastCheck {
+                checked_at,
+                error: update.as_ref().err().map(|error| {
+                    format!("{error:#}").replace(
+                        &std::env::current_dir().unwrap().display().to_string(),
+                        "$PWD",
+                    )
+                }),
+            },
+        );
+        let Ok(Some(update)) = update else {
+            continue;
+        };
+        let count = |kind| {
+            update
+                .file_changes
+                .iter()
+                .filter(|c| c.kind == kind)
+                .count()
+        };
+        let manifest = update.manifest_changes.as_ref();
+        let endpoints = update.endpoint_changes.as_ref();
+        history.updates.push(HistoryEntry {
+            name: extension.name.clone(),
+            display_name: extension.display_name.clone(),
+            prev_version: update.prev_version.clone(),
+            cur_version: update.cur_version.clone(),
+            checked_at,
+            version_change: update.version_change().to_string(),
+            severity: update.severity().to_string(),
+            files_added: count(ChangeKind::Added),
+            files_removed: count(ChangeKind::Removed),
+            files_modified: count(ChangeKind::Modified),
+            added_permissions: manifest
+                .map(|m| m.added_permissions.clone())
+                .unwrap_or_default(),
+            removed_permissions: manifest
+                .map(|m| m.removed_permissions.clone())
+                .unwrap_or_default(),
+            changed_keys: manifest
+                .map(|m| m.changed_keys.iter().map(|(key, ..)| key.clone()).collect())
+                .unwrap_or_default(),
+            added_endpoints: endpoints.map_or(0, |e| e.added.labeled().count()),
+            removed_endpoints: endpoints.map_or(0, |e| e.removed.labeled().count()),
+            secrets: update.secrets.iter().map(|s| s.kind.clone()).collect(),
+            diffs: update
+                .diff_files(extension)
+                .into_iter()
+                .map(|(filename, _)| filename)
+                .collect(),
+        });
+    }
+
+    if let Err(error) = tokio::fs::write(
+        HISTORY_PATH,
+        format!("{HISTORY_HEADER}{}", toml::to_string(&history).unwrap()),
+    )
+    .await
+    {
+        warn!(%error, "failed to write history.toml");
+    }
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc3339_timestamps() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1694714580), "2023-09-14T18:03:00Z");
+        assert_eq!(rfc3339(946684799), "1999-12-31T23:59:59Z");
+        // january and february count as the end of the previous year
+        assert_eq!(rfc3339(1707548709), "2024-02-10T07:05:09Z");
+        // leap days
+        assert_eq!(rfc3339(1709210096), "2024-02-29T12:34:56Z");
+        assert_eq!(rfc3339(951868799), "2000-02-29T23:59:59Z");
+        // 2100 isn't a leap year
+        assert_eq!(rfc3339(4107542400), "2100-03-01T00:00:00Z");
+    }
+}
@@ -0,0 +1,68 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::{bail, Context, Result};
+use minijinja::{Environment, Value};
+use serde::Deserialize;
+
+/// the default templates of the notifiers. see docs/templates.md
+pub const DISCORD_TEMPLATE: &str = include_str!("../templates/discord.md");
+pub const SLACK_TEMPLATE: &str = include_str!("../templates/slack.md");
+pub const MARKDOWN_TEMPLATE: &str = include_str!("../templates/markdown.md");
+pub const PUSH_TEMPLATE: &str = include_str!("../templates/push.md");
+
+/// a custom template for a notifier's update messages
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateConfig {
+    template: Option<String>,
+    /// a file with the template, relative to the current working directory
+    template_file: Option<String>,
+}
+
+impl TemplateConfig {
+    /// reads template_file and checks that the template is valid
+    pub async fn load(&mut self) -> Result<()> {
+        if let Some(ref template_file) = self.template_file {
+            if self.template.is_some() {
+                bail!("notifiers can't have both a template and a template_file");
+            }
+            self.template = Some(
+                tokio::fs::read_to_string(template_file)
+                    .await
+                    .with_context(|| format!("couldn't read {template_file}"))?,
+            );
+        }
+        if let Some(ref template) = self.template {
+            Environment::new()
+                .template_from_str(template)
+                .context("invalid template")?;
+        }
+        Ok(())
+    }
+
+    /// the template, after it is loaded
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
+}
+
+pub fn render_template(template: &str, context: &Value) -> Result<String> {
+    Environment::new()
+        .render_str(template, context)
+        .context("couldn't render template")
+}
@@ -0,0 +1,281 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::default_true;
+
+/// patterns that are always checked unless `use_builtin_patterns` is false.
+/// if a pattern has a capture group, the first group is used as the secret's value.
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("google_api_key", r"AIza[0-9A-Za-z\-_]{35}"),
+    (
+        "aws_access_key_id",
+        r"\b(?:A3T[A-Z0-9]|AKIA|ASIA|ABIA|ACCA)[A-Z0-9]{16}\b",
+    ),
+    (
+        "aws_secret_access_key",
+        r#"(?i)aws_?secret_?access_?key["'`]?\s*[:=]\s*["'`]([A-Za-z0-9/+]{40})["'`]"#,
+    ),
+    (
+        "sentry_dsn",
+        r"https?://[0-9a-f]{32}(?::[0-9a-f]{32})?@[a-z0-9.\-]+/\d+",
+    ),
+    (
+        "firebase_config",
+        r"[a-z0-9\-]+\.(?:firebaseio\.com|firebaseapp\.com)",
+    ),
+    (
+        "jwt",
+        r"eyJ[A-Za-z0-9_\-]{10,}\.eyJ[A-Za-z0-9_\-]{10,}\.[A-Za-z0-9_\-]{10,}",
+    ),
+    (
+        "private_key",
+        r"-----BEGIN (?:RSA |EC |DSA |OPENSSH )?PRIVATE KEY-----",
+    ),
+];
+
+/// how many bytes at the start of a file are checked for NUL bytes to detect binary files
+const BINARY_CHECK_LEN: usize = 8192;
+
+/// secrets longer than this are shortened in update messages
+const MAX_DISPLAYED_LEN: usize = 64;
+
+const fn default_entropy_threshold() -> f64 {
+    4.5
+}
+
+const fn default_entropy_min_length() -> usize {
+    20
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SecretsConfig {
+    #[serde(default = "default_true")]
+    use_builtin_patterns: bool,
+    #[serde(default)]
+    patterns: BTreeMap<String, String>,
+    #[serde(default = "default_true")]
+    entropy: bool,
+    #[serde(default = "default_entropy_threshold")]
+    entropy_threshold: f64,
+    #[serde(default = "default_entropy_min_length")]
+    entropy_min_length: usize,
+}
+
+#[derive(Debug)]
+pub struct Secret {
+    /// the name of the pattern that matched, or "high_entropy_string"
+    pub kind: String,
+    pub value: String,
+    /// path of the file relative to the extracted extension directory
+    pub file: String,
+    pub line: usize,
+}
+
+impl Secret {
+    pub fn displayed_value(&self) -> String {
+        if self.value.chars().count() > MAX_DISPLAYED_LEN {
+            format!(
+                "{}…",
+                self.value
+                    .chars()
+                    .take(MAX_DISPLAYED_LEN)
+                    .collect::<String>()
+            )
+        } else {
+            self.value.clone()
+        }
+    }
+}
+
+struct Scanner {
+    patterns: Vec<(String, Regex)>,
+    entropy_candidates: Option<Regex>,
+    entropy_threshold: f64,
+}
+
+impl Scanner {
+    fn new(config: &SecretsConfig) -> Result<Self> {
+        let mut patterns = vec![];
+        if config.use_builtin_patterns {
+            for (name, pattern) in BUILTIN_PATTERNS {
+                patterns.push((name.to_string(), Regex::new(pattern).unwrap()));
+            }
+        }
+        for (name, pattern) in &config.patterns {
+            patterns.push((
+                name.clone(),
+                Regex::new(pattern)
+                    .with_context(|| format!("invalid secret pattern {name}: {pattern}"))?,
+            ));
+        }
+
+        let entropy_candidates = if config.entropy {
+            Some(
+                Regex::new(&format!(
+                    r#"["'`]([A-Za-z0-9+/=_\-]{{{},}})["'`]"#,
+                    config.entropy_min_length
+                ))
+                .context("invalid entropy_min_length")?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            patterns,
+            entropy_candidates,
+            entropy_threshold: config.entropy_threshold,
+        })
+    }
+
+    fn scan_dir(&self, dir: &Path) -> Vec<Secret> {
+        let mut secrets = vec![];
+        for entry in WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let contents = match std::fs::read(entry.path()) {
+                Ok(c) => c,
+                Err(error) => {
+                    warn!(%error, path = %entry.path().display(), "failed to read file");
+                    continue;
+                }
+            };
+            if contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0) {
+                continue;
+            }
+            let contents = String::from_utf8_lossy(&contents);
+            let file = entry
+                .path()
+                .strip_prefix(dir)
+                .unwrap_or(entry.path())
+                .display()
+                .to_string();
+            self.scan_file(&file, &contents, &mut secrets);
+        }
+        secrets
+    }
+
+    fn scan_file(&self, file: &str, contents: &str, secrets: &mut Vec<Secret>) {
+        let newlines = contents
+            .match_indices('\n')
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let line_of = |offset: usize| newlines.partition_point(|&i| i < offset) + 1;
+
+        let mut matched = HashSet::new();
+        for (name, pattern) in &self.patterns {
+            for captures in pattern.captures_iter(contents) {
+                let m = captures.get(1).unwrap_or_else(|| captures.get(0).unwrap());
+                matched.insert(m.as_str());
+                secrets.push(Secret {
+                    kind: name.clone(),
+                    value: m.as_str().into(),
+                    file: file.into(),
+                    line: line_of(m.start()),
+                });
+            }
+        }
+
+        if let Some(ref entropy_candidates) = self.entropy_candidates {
+            for captures in entropy_candidates.captures_iter(contents) {
+                let m = captures.get(1).unwrap();
+                let value = m.as_str();
+                if matched.contains(value)
+                    || !value.chars().any(|c| c.is_ascii_digit())
+                    || !value.chars().any(|c| c.is_ascii_alphabetic())
+                    || shannon_entropy(value) < self.entropy_threshold
+                {
+                    continue;
+                }
+                secrets.push(Secret {
+                    kind: "high_entropy_string".into(),
+                    value: value.into(),
+                    file: file.into(),
+                    line: line_of(m.start()),
+                });
+            }
+        }
+    }
+}
+
+fn shannon_entropy(value: &str) -> f64 {
+    let mut counts = BTreeMap::new();
+    for c in value.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+    }
+    let len = value.chars().count() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// scans ./crx/{name}-{cur_version} for secrets that do not appear anywhere in ./crx/{name}-{prev_version}.
+/// each new secret is only reported once, at its first occurrence. if the previous version isn't known or was pruned,
+/// nothing is reported, since every secret would look new
+#[tracing::instrument(skip(config), err)]
+pub async fn scan_secrets(
+    config: &SecretsConfig,
+    name: &str,
+    prev_version: &str,
+    cur_version: &str,
+) -> Result<Vec<Secret>> {
+    let scanner = Scanner::new(config)?;
+    let prev_dir = format!("./crx/{name}-{prev_version}");
+    let cur_dir = format!("./crx/{name}-{cur_version}");
+    if prev_version == "None" || !Path::new(&prev_dir).is_dir() {
+        debug!("no previous version to compare against, skipping");
+        return Ok(vec![]);
+    }
+    let secrets = tokio::task::spawn_blocking(move || {
+        let prev_values = scanner
+            .scan_dir(Path::new(&prev_dir))
+            .into_iter()
+            .map(|s| s.value)
+            .collect::<HashSet<_>>();
+
+        let mut reported = HashSet::new();
+        let mut secrets = scanner
+            .scan_dir(Path::new(&cur_dir))
+            .into_iter()
+            .filter(|s| !prev_values.contains(&s.value))
+            .filter(|s| reported.insert(s.value.clone()))
+            .collect::<Vec<_>>();
+        secrets.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        secrets
+    })
+    .await
+    .unwrap();
+    debug!(count = secrets.len(), "found new secrets");
+    Ok(secrets)
+}
@@ -0,0 +1,319 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anyhow::{bail, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Deserialize;
+use tracing::{debug, error, info, Instrument};
+
+use crate::attachments::AttachmentsConfig;
+use crate::notify::{summarize, to_html, Notifier, Report};
+use crate::render_template::MARKDOWN_TEMPLATE;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tls {
+    /// connect without tls and upgrade the connection with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// connect with tls, usually on port 465
+    Implicit,
+    /// don't use tls. only for local mail servers
+    None,
+}
+
+/// the username and password of the smtp server, kept out of config.toml
+#[derive(Deserialize)]
+struct SmtpSecrets {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecipientList {
+    #[serde(default)]
+    to: Vec<String>,
+    #[serde(default)]
+    cc: Vec<String>,
+    #[serde(default)]
+    bcc: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailConfig {
+    /// the hostname of the smtp server
+    host: String,
+    /// defaults to the standard port for tls
+    port: Option<u16>,
+    #[serde(default)]
+    tls: Tls,
+    /// a toml file with the username and password of the smtp server. if this isn't set, no authentication is used
+    secrets_file: Option<String>,
+    /// the sender, like "Extension Version Watcher <watcher@example.com>"
+    from: String,
+    /// one email is sent to each list
+    recipients: Vec<RecipientList>,
+    #[serde(flatten)]
+    attachments: AttachmentsConfig,
+}
+
+fn parse_mailboxes(addresses: &[String]) -> Result<Vec<Mailbox>> {
+    addresses
+        .iter()
+        .map(|address| {
+            address
+                .parse()
+                .with_context(|| format!("invalid email address {address}"))
+        })
+        .collect()
+}
+
+impl EmailConfig {
+    pub fn validate(&self) -> Result<()> {
+        self.from
+            .parse::<Mailbox>()
+            .with_context(|| format!("invalid email address {}", self.from))?;
+        for list in &self.recipients {
+            parse_mailboxes(&list.to)?;
+            parse_mailboxes(&list.cc)?;
+            parse_mailboxes(&list.bcc)?;
+            if list.to.is_empty() && list.cc.is_empty() && list.bcc.is_empty() {
+                bail!("email recipient lists can't be empty");
+            }
+        }
+        Ok(())
+    }
+
+    async fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let mut builder = match self.tls {
+            Tls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)
+                .context("couldn't set up STARTTLS")?,
+            Tls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)
+                .context("couldn't set up tls")?,
+            Tls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+        };
+        if let Some(port) = self.port {
+            builder = builder.port(port);
+        }
+        if let Some(ref secrets_file) = self.secrets_file {
+            let secrets = tokio::fs::read_to_string(secrets_file)
+                .await
+                .with_context(|| format!("couldn't read {secrets_file}"))?;
+            let secrets: SmtpSecrets = toml::from_str(&secrets)
+                .with_context(|| format!("couldn't deserialize {secrets_file}"))?;
+            builder = builder.credentials(Credentials::new(secrets.username, secrets.password));
+        }
+        Ok(builder.build())
+    }
+}
+
+impl Notifier for EmailConfig {
+    #[tracing::instrument(name = "send_email", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(mut summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let (attachments, notes) = self
+            .attachments
+            .prepare(std::mem::take(&mut summary.attachments));
+        let text = report.render(MARKDOWN_TEMPLATE, &summary, &notes, false);
+        let html = format!(
+            "<!DOCTYPE html><html><body style=\"font-family: sans-serif\">{}</body></html>",
+            to_html(&text)
+        );
+        let subject = summary.title();
+
+        let transport = match self.transport().await {
+            Ok(transport) => transport,
+            Err(error) => {
+                error!("failed to connect to the smtp server: {error:#}");
+                return;
+            }
+        };
+        for (i, list) in self.recipients.iter().enumerate() {
+            async {
+                let mut builder = Message::builder()
+                    .from(self.from.parse().unwrap())
+                    .subject(&subject);
+                // the addresses were checked when the config was loaded
+                for mailbox in parse_mailboxes(&list.to).unwrap() {
+                    builder = builder.to(mailbox);
+                }
+                for mailbox in parse_mailboxes(&list.cc).unwrap() {
+                    builder = builder.cc(mailbox);
+                }
+                for mailbox in parse_mailboxes(&list.bcc).unwrap() {
+                    builder = builder.bcc(mailbox);
+                }
+                let mut body = MultiPart::mixed().multipart(MultiPart::alternative_plain_html(
+                    text.clone(),
+                    html.clone(),
+                ));
+                for attachment in &attachments {
+                    body = body.singlepart(
+                        lettre::message::Attachment::new(attachment.filename.clone()).body(
+                            attachment.data.clone(),
+                            ContentType::parse(attachment.content_type()).unwrap(),
+                        ),
+                    );
+                }
+                let message = match builder.multipart(body) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        error!("failed to build email: {error}");
+                        return;
+                    }
+                };
+
+                debug!("sending email");
+                match transport.send(message).await {
+                    Ok(_) => info!("sent email"),
+                    Err(error) => error!("failed to send email: {error}"),
+                }
+            }
+            .instrument(tracing::info_span!("recipients", i))
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::notify::updated_extension;
+
+    /// the commands an smtp client sent, and the data of each email
+    #[derive(Debug, Default)]
+    struct Session {
+        commands: Vec<String>,
+        data: Vec<String>,
+    }
+
+    /// accepts one smtp session on a random local port
+    async fn smtp_server() -> (u16, tokio::task::JoinHandle<Session>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut session = Session::default();
+            stream
+                .get_mut()
+                .write_all(b"220 localhost\r\n")
+                .await
+                .unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return session;
+                }
+                let command = line.trim_end().to_string();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-localhost\r\n250 8BITMIME\r\n"
+                } else if command == "DATA" {
+                    stream
+                        .get_mut()
+                        .write_all(b"354 go ahead\r\n")
+                        .await
+                        .unwrap();
+                    let mut data = String::new();
+                    loop {
+                        let mut line = String::new();
+                        stream.read_line(&mut line).await.unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    session.data.push(data);
+                    b"250 queued\r\n"
+                } else if command == "QUIT" {
+                    stream.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                    session.commands.push(command);
+                    return session;
+                } else {
+                    b"250 OK\r\n"
+                };
+                session.commands.push(command);
+                stream.get_mut().write_all(reply).await.unwrap();
+            }
+        });
+        (port, server)
+    }
+
+    #[tokio::test]
+    async fn sends_email_with_attachments() {
+        let (port, server) = smtp_server().await;
+        let config: EmailConfig = toml::from_str(&format!(
+            r#"
+            host = "127.0.0.1"
+            port = {port}
+            tls = "none"
+            from = "Watcher <watcher@example.com>"
+            [[recipients]]
+            to = ["a@example.com"]
+            cc = ["b@example.com"]
+            bcc = ["c@example.com"]
+            "#
+        ))
+        .unwrap();
+        config.validate().unwrap();
+
+        let checked = updated_extension("foo", Some("-old\n+new\n"));
+        config
+            .notify(&Report {
+                checked_extensions: vec![&checked],
+                error_events: &HashMap::new(),
+                mention: None,
+                template: None,
+            })
+            .await;
+        let session = server.await.unwrap();
+
+        assert!(session
+            .commands
+            .contains(&"MAIL FROM:<watcher@example.com>".into()));
+        for recipient in ["a@example.com", "b@example.com", "c@example.com"] {
+            assert!(session.commands.contains(&format!("RCPT TO:<{recipient}>")));
+        }
+        assert_eq!(session.data.len(), 1);
+        let data = &session.data[0];
+        assert!(data.contains("Subject: Extension updates: 1 updated\r\n"));
+        assert!(data.contains("To: a@example.com\r\n"));
+        assert!(data.contains("Cc: b@example.com\r\n"));
+        // bcc recipients must not be visible to the others
+        assert!(!data.contains("c@example.com"));
+        assert!(data.contains("Content-Type: multipart/mixed;"));
+        assert!(data.contains("Content-Type: multipart/alternative;"));
+        assert!(data.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(data.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(data.contains("FOO"));
+        assert!(data.contains("Content-Disposition: attachment; filename=\"foo-1.0-1.1.diff\""));
+        assert!(data.contains("Content-Type: text/x-diff"));
+        assert!(data.replace("\r\n", "\n").contains("\n-old\n+new\n"));
+    }
+}
@@ -0,0 +1,296 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Debug;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::classify_update::Severity;
+use crate::notify::{split_message, summarize, Notifier, Report, Summary};
+use crate::render_template::PUSH_TEMPLATE;
+
+/// ntfy turns messages larger than 4096 bytes into attachments, so push notifications are kept under that
+const MAX_BODY_LEN: usize = 3800;
+
+/// the priority of a notification for each severity of update. if there are errors, the error priority is used if it
+/// is higher
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Priorities {
+    low: u8,
+    medium: u8,
+    high: u8,
+    error: u8,
+}
+
+impl Priorities {
+    fn of(&self, summary: &Summary) -> u8 {
+        let updates = match summary.severity {
+            Some(Severity::Low) => self.low,
+            Some(Severity::Medium) => self.medium,
+            Some(Severity::High) => self.high,
+            None => 0,
+        };
+        if summary.errors.is_empty() {
+            updates
+        } else {
+            updates.max(self.error)
+        }
+    }
+}
+
+/// ntfy's priorities go from 1 (min) to 5 (max), with 3 as the default
+const fn default_ntfy_priorities() -> Priorities {
+    Priorities {
+        low: 3,
+        medium: 4,
+        high: 5,
+        error: 4,
+    }
+}
+
+/// gotify's priorities go from 0 to 10. clients usually only make a sound for 4 and above, and ignore the
+/// notification completely below 1
+const fn default_gotify_priorities() -> Priorities {
+    Priorities {
+        low: 4,
+        medium: 6,
+        high: 8,
+        error: 6,
+    }
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".into()
+}
+
+#[derive(Deserialize)]
+pub struct NtfyConfig {
+    #[serde(default = "default_ntfy_server")]
+    server: String,
+    topic: String,
+    /// an access token, for topics that need authentication
+    token: Option<String>,
+    #[serde(default = "default_ntfy_priorities")]
+    priorities: Priorities,
+}
+
+impl Debug for NtfyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the topic works like a password on public servers, and the token is secret
+        f.debug_struct("NtfyConfig")
+            .field("server", &self.server)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GotifyConfig {
+    /// the base url of the gotify server
+    server: String,
+    /// the token of the application that sends the notifications
+    token: String,
+    #[serde(default = "default_gotify_priorities")]
+    priorities: Priorities,
+}
+
+impl Debug for GotifyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the token is secret
+        f.debug_struct("GotifyConfig")
+            .field("server", &self.server)
+            .finish_non_exhaustive()
+    }
+}
+
+/// push notifications only list the updates, recoveries and errors by default, and are cut off if they are too long
+fn body(report: &Report, summary: &Summary) -> String {
+    let text = report.render(PUSH_TEMPLATE, summary, &[], false);
+    let mut chunks = split_message(&text, MAX_BODY_LEN).into_iter();
+    let mut body = chunks.next().unwrap_or_default();
+    if chunks.next().is_some() {
+        body.push_str("\n- ...");
+    }
+    body
+}
+
+async fn check_response(response: reqwest::Response) -> Result<()> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("server responded with {status}: {body}");
+    }
+    Ok(())
+}
+
+impl Notifier for NtfyConfig {
+    #[tracing::instrument(name = "send_to_ntfy", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let mut request = Client::new()
+            .post(format!(
+                "{}/{}",
+                self.server.trim_end_matches('/'),
+                self.topic
+            ))
+            .header("Title", summary.title())
+            .header("Priority", self.priorities.of(&summary).to_string())
+            .header("Tags", "extension-version-watcher")
+            .header("Markdown", "yes")
+            .body(body(report, &summary));
+        if let Some(ref token) = self.token {
+            request = request.bearer_auth(token);
+        }
+        let result = async {
+            let response = request
+                .send()
+                .await
+                // the url contains the topic
+                .map_err(reqwest::Error::without_url)
+                .context("couldn't publish notification")?;
+            check_response(response).await
+        }
+        .await;
+        match result {
+            Ok(_) => info!("sent push notification"),
+            Err(error) => error!("failed to send push notification: {error:#}"),
+        }
+    }
+}
+
+impl Notifier for GotifyConfig {
+    #[tracing::instrument(name = "send_to_gotify", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let result = async {
+            let response = Client::new()
+                .post(format!("{}/message", self.server.trim_end_matches('/')))
+                .header("X-Gotify-Key", &self.token)
+                .json(&json!({
+                    "title": summary.title(),
+                    "message": body(report, &summary),
+                    "priority": self.priorities.of(&summary),
+                    "extras": { "client::display": { "contentType": "text/markdown" } },
+                }))
+                .send()
+                .await
+                .context("couldn't create message")?;
+            check_response(response).await
+        }
+        .await;
+        match result {
+            Ok(_) => info!("sent push notification"),
+            Err(error) => error!("failed to send push notification: {error:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::notify::{updated_extension, CheckedExtension};
+    use crate::test_server::serve;
+    use crate::track_errors::ErrorEvent;
+
+    fn report<'a>(
+        checked: &'a [CheckedExtension],
+        error_events: &'a HashMap<String, ErrorEvent>,
+    ) -> Report<'a> {
+        Report {
+            checked_extensions: checked.iter().collect(),
+            error_events,
+            mention: None,
+            template: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_to_ntfy() {
+        let (url, mut requests) = serve("{}").await;
+        let config: NtfyConfig = toml::from_str(&format!(
+            r#"
+            server = "{url}/"
+            topic = "updates"
+            token = "tk_secret"
+            "#
+        ))
+        .unwrap();
+        let checked = [updated_extension("foo", Some("-old\n+new\n"))];
+        config.notify(&report(&checked, &HashMap::new())).await;
+
+        let request = requests.try_recv().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/updates");
+        assert_eq!(request.header("authorization"), Some("Bearer tk_secret"));
+        assert_eq!(
+            request.header("title"),
+            Some("Extension updates: 1 updated")
+        );
+        assert_eq!(request.header("priority"), Some("3"));
+        assert_eq!(request.header("markdown"), Some("yes"));
+        assert!(request.text().contains("FOO"));
+        assert!(request.text().contains("1.0"));
+        assert!(request.text().contains("1.1"));
+        // diffs are never sent in push notifications
+        assert!(!request.text().contains("+new"));
+    }
+
+    #[tokio::test]
+    async fn creates_gotify_message() {
+        let (url, mut requests) = serve("{}").await;
+        let config: GotifyConfig = toml::from_str(&format!(
+            r#"
+            server = "{url}"
+            token = "app_token"
+            "#
+        ))
+        .unwrap();
+        let checked = (0..1000)
+            .map(|i| updated_extension(&format!("extension-{i}"), None))
+            .collect::<Vec<_>>();
+        config.notify(&report(&checked, &HashMap::new())).await;
+
+        let request = requests.try_recv().unwrap();
+        assert_eq!(request.path, "/message");
+        assert_eq!(request.header("x-gotify-key"), Some("app_token"));
+        let body = request.json();
+        assert_eq!(body["title"], "Extension updates: 1000 updated");
+        assert_eq!(body["priority"], 4);
+        assert_eq!(
+            body["extras"]["client::display"]["contentType"],
+            "text/markdown"
+        );
+        // long messages are cut off
+        let message = body["message"].as_str().unwrap();
+        assert!(message.len() <= MAX_BODY_LEN + "\n- ...".len());
+        assert!(message.contains("EXTENSION-0"));
+        assert!(!message.contains("EXTENSION-999"));
+        assert!(message.ends_with("\n- ..."));
+    }
+}
@@ -16,115 +16,290 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use tracing::{debug, error, info, Instrument};
 use twilight_http::Client;
-use twilight_model::{http::attachment::Attachment, id::Id};
-
-use crate::get_update::Update;
-use crate::{extensions::Extension, DiscordConfig};
-
-#[tracing::instrument(skip(checked_extensions))]
-pub async fn send_to_discord(
-    config: DiscordConfig,
-    checked_extensions: &Vec<(Extension, Result<Option<Update>>)>,
-) {
-    let mut updates_text = vec![];
-    let mut errors_text = vec![];
-    let mut attachments = vec![];
-    let mut attachment_id = 0;
-
-    for (extension, update) in checked_extensions {
-        match update {
-            Ok(ref update) => {
-                if let Some(ref update) = update {
-                    updates_text.push(format!(
-                        "- {}: `{}` -> `{}`{}",
-                        extension.display_name,
-                        update.prev_version,
-                        update.cur_version,
-                        if update.diff.is_some() {
-                            " (diff automatically generated)"
-                        } else {
-                            ""
-                        }
-                    ));
-                    if let Some(diff) = &update.diff {
-                        let filename = format!(
-                            "{}-{}-{}.diff",
-                            extension.name, update.prev_version, update.cur_version
-                        );
-                        attachments.push(Attachment {
-                            description: None,
-                            file: diff.as_bytes().to_vec(),
-                            filename: filename.clone(),
-                            id: attachment_id,
-                        });
-                        attachment_id += 1;
-
-                        if let Err(error) =
-                            tokio::fs::write(format!("./diff/{filename}"), diff).await
-                        {
-                            error!(%error, filename, "failed to write diff file");
-                        }
-                    }
-                }
+use twilight_model::{channel::message::embed::Embed, http::attachment::Attachment, id::Id};
+
+use crate::attachments;
+use crate::discord_embeds::{
+    embed_len, errors_embed, recovered_embed, update_embed, MAX_EMBEDS, MAX_TOTAL_LEN,
+};
+use crate::execute_webhook::{execute_webhook, WebhookConfig};
+use crate::notify::{split_message, summarize, Notifier, Report};
+use crate::render_template::DISCORD_TEMPLATE;
+use crate::DiscordConfig;
+
+/// discord's limit on the length of a message's content, in characters
+const MAX_MESSAGE_LEN: usize = 2000;
+
+/// discord's limit on the number of attachments per message
+const MAX_ATTACHMENTS: usize = 10;
+
+/// sent in place of attachments if a message couldn't be sent with them
+const ATTACHMENTS_FAILED_NOTE: &str = "*Attachments couldn't be sent, see `./diff` on the host.*";
+
+/// one discord message
+#[derive(Default)]
+struct Post {
+    content: String,
+    embeds: Vec<Embed>,
+    attachments: Vec<attachments::Attachment>,
+}
+
+/// splits the update message, embeds and attachments into the messages that are sent to each channel.
+/// the embeds and attachments are sent with the last part of the message, and with extra messages after it if there are too many
+fn split_posts(
+    message: &str,
+    embeds: Vec<Embed>,
+    attachments: Vec<attachments::Attachment>,
+) -> Vec<Post> {
+    let mut posts = split_message(message, MAX_MESSAGE_LEN)
+        .into_iter()
+        .map(|content| Post {
+            content,
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut embed_groups: Vec<Vec<Embed>> = vec![];
+    let mut group_len = 0;
+    for embed in embeds {
+        let len = embed_len(&embed);
+        match embed_groups.last_mut() {
+            Some(group) if group.len() < MAX_EMBEDS && group_len + len <= MAX_TOTAL_LEN => {
+                group.push(embed);
+                group_len += len;
             }
-            Err(error) => {
-                errors_text.push(format!("- {}: {error}", extension.display_name).replace(
-                    &std::env::current_dir().unwrap().display().to_string(),
-                    "$PWD",
-                ))
+            _ => {
+                embed_groups.push(vec![embed]);
+                group_len = len;
             }
         }
     }
+    for group in embed_groups {
+        match posts.last_mut() {
+            Some(last) if last.embeds.is_empty() => last.embeds = group,
+            _ => posts.push(Post {
+                embeds: group,
+                ..Default::default()
+            }),
+        }
+    }
 
-    if updates_text.is_empty() && errors_text.is_empty() {
-        info!("no updates or errors");
-        return;
+    let mut attachments = attachments.into_iter().peekable();
+    while attachments.peek().is_some() {
+        let group = attachments.by_ref().take(MAX_ATTACHMENTS).collect();
+        match posts.last_mut() {
+            Some(last) if last.attachments.is_empty() => last.attachments = group,
+            _ => posts.push(Post {
+                attachments: group,
+                ..Default::default()
+            }),
+        }
     }
-    let newlines = if !updates_text.is_empty() && !errors_text.is_empty() {
-        "\n\n"
-    } else {
-        ""
-    };
-    let updates_text = updates_text.join("\n");
-    let errors_text = if !errors_text.is_empty() {
-        format!(
-            "The following errors occurred:\n\n{}",
-            errors_text.join("\n")
-        )
-    } else {
-        "".into()
-    };
-    let update_message = format!(
-    "**__Extension Updates__**
+    posts
+}
 
-{updates_text}{newlines}{errors_text}
+impl Notifier for DiscordConfig {
+    #[tracing::instrument(name = "send_to_discord", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(mut summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
 
-> *🤖 Automated by <@1019305439000801311>. Please ping them for any questions or suggestions (don't expect them to respond quickly).*
-> *Open source at <https://github.com/staticallyamazing/extension-version-watcher>.*
-> *Version: {}*", env!("CARGO_PKG_VERSION"));
+        let mut embeds = vec![];
+        if self.embeds {
+            for (extension, update) in &report.checked_extensions {
+                if let Ok(Some(ref update)) = update {
+                    embeds.push(update_embed(extension, update));
+                }
+            }
+            if !summary.recovered.is_empty() {
+                embeds.push(recovered_embed(&summary.recovered));
+            }
+            if !summary.errors.is_empty() {
+                embeds.push(errors_embed(&summary.errors));
+            }
+        }
+
+        let (attachments, attachment_notes) = self
+            .attachments
+            .prepare(std::mem::take(&mut summary.attachments));
+        // with embeds, the embeds replace everything but the attachment notes and the mention
+        let update_message =
+            report.render(DISCORD_TEMPLATE, &summary, &attachment_notes, self.embeds);
+
+        let posts = split_posts(&update_message, embeds, attachments);
+        debug!(posts = posts.len(), "split update message");
+        if let Some(ref token) = self.token {
+            let client = Client::new(token.clone());
+            for &channel in &self.channel_ids {
+                send_posts(&Destination::Channel(&client, channel), &posts)
+                    .instrument(tracing::info_span!("channel", channel))
+                    .await
+            }
+        }
+        let client = reqwest::Client::new();
+        for (i, webhook) in self.webhooks.iter().enumerate() {
+            send_posts(&Destination::Webhook(&client, webhook), &posts)
+                .instrument(tracing::info_span!("webhook", i))
+                .await
+        }
+    }
+}
 
-    let client = Client::new(config.token);
-    let attachments = attachments.as_slice();
-    for channel in config.channel_ids {
+/// where update messages are sent
+enum Destination<'a> {
+    Channel(&'a Client, u64),
+    Webhook(&'a reqwest::Client, &'a WebhookConfig),
+}
+
+impl Destination<'_> {
+    async fn send(
+        &self,
+        content: &str,
+        embeds: &[Embed],
+        attachments: &[attachments::Attachment],
+    ) -> Result<()> {
+        match *self {
+            Destination::Channel(client, channel) => {
+                create_message(client, channel, content, embeds, attachments).await
+            }
+            Destination::Webhook(client, webhook) => {
+                execute_webhook(client, webhook, content, embeds, attachments).await
+            }
+        }
+    }
+}
+
+async fn send_posts(destination: &Destination<'_>, posts: &[Post]) {
+    for (i, post) in posts.iter().enumerate() {
         async {
             debug!("sending update message");
-            if let Err(error) = client
-                .create_message(Id::new(channel))
-                .attachments(attachments)
-                .unwrap()
-                .content(&update_message)
-                .unwrap()
+            let error = match destination
+                .send(&post.content, &post.embeds, &post.attachments)
                 .await
             {
-                error!(?error, "failed to send update message");
-            } else {
-                info!("sent update message");
+                Ok(_) => {
+                    info!("sent update message");
+                    return;
+                }
+                Err(error) => error,
+            };
+            if post.attachments.is_empty() {
+                error!("failed to send update message: {error:#}");
+                return;
+            }
+
+            // the attachments are the most likely reason the message couldn't be sent, so try again without them
+            error!("failed to send update message, retrying without attachments: {error:#}");
+            let retry_message = format!("{}\n\n{ATTACHMENTS_FAILED_NOTE}", post.content);
+            let mut embeds = post.embeds.as_slice();
+            for content in split_message(&retry_message, MAX_MESSAGE_LEN) {
+                if let Err(error) = destination.send(&content, embeds, &[]).await {
+                    error!("failed to send update message without attachments: {error:#}");
+                    return;
+                }
+                embeds = &[];
             }
+            info!("sent update message without attachments");
         }
-        .instrument(tracing::info_span!("channel", channel))
+        .instrument(tracing::info_span!("post", i))
         .await
     }
 }
+
+async fn create_message(
+    client: &Client,
+    channel: u64,
+    content: &str,
+    embeds: &[Embed],
+    attachments: &[attachments::Attachment],
+) -> Result<()> {
+    let attachments = attachments
+        .iter()
+        .enumerate()
+        .map(|(id, attachment)| Attachment {
+            description: None,
+            file: attachment.data.clone(),
+            filename: attachment.filename.clone(),
+            id: id as u64,
+        })
+        .collect::<Vec<_>>();
+    let request = client
+        .create_message(Id::new(channel))
+        .attachments(&attachments)
+        .unwrap()
+        .embeds(embeds)
+        .context("invalid embeds")?;
+    if content.is_empty() {
+        request.await?;
+    } else {
+        // split_message keeps the content within discord's limit
+        request.content(content).unwrap().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(len: usize) -> Embed {
+        errors_embed(&["x".repeat(len)])
+    }
+
+    fn attachment(i: usize) -> attachments::Attachment {
+        attachments::Attachment {
+            filename: format!("{i}.diff"),
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn long_messages_are_split() {
+        let message = format!(
+            "{}\n{}\n{}",
+            "a".repeat(1500),
+            "b".repeat(1500),
+            "c".repeat(2500)
+        );
+        let posts = split_posts(&message, vec![errors(10)], vec![attachment(0)]);
+        let contents = posts
+            .iter()
+            .map(|p| p.content.chars().count())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, [1500, 1500, 2000, 500]);
+        // embeds and attachments are sent with the last part of the message
+        assert!(posts[..3]
+            .iter()
+            .all(|p| p.embeds.is_empty() && p.attachments.is_empty()));
+        assert_eq!(posts[3].embeds.len(), 1);
+        assert_eq!(posts[3].attachments.len(), 1);
+    }
+
+    #[test]
+    fn extra_posts_for_embeds_and_attachments() {
+        let posts = split_posts(
+            "message",
+            (0..12).map(|_| errors(10)).collect(),
+            (0..12).map(attachment).collect(),
+        );
+        let shape = posts
+            .iter()
+            .map(|p| (p.content.as_str(), p.embeds.len(), p.attachments.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(shape, [("message", 10, 0), ("", 2, 10), ("", 0, 2)]);
+        assert_eq!(posts[2].attachments[0].filename, "10.diff");
+
+        // embeds are grouped so that each message stays under discord's total length limit
+        let posts = split_posts("", (0..3).map(|_| errors(2500)).collect(), vec![]);
+        let groups = posts.iter().map(|p| p.embeds.len()).collect::<Vec<_>>();
+        assert_eq!(groups, [2, 1]);
+        for post in &posts {
+            assert!(post.embeds.iter().map(embed_len).sum::<usize>() <= MAX_TOTAL_LEN);
+        }
+    }
+}
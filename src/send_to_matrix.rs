@@ -0,0 +1,197 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, Instrument};
+
+use crate::attachments::{Attachment, AttachmentsConfig};
+use crate::notify::{split_message, summarize, to_html, Notifier, Report};
+use crate::render_template::MARKDOWN_TEMPLATE;
+
+/// events can be at most 65536 bytes, and the html version of the message is sent along with the text
+const MAX_MESSAGE_LEN: usize = 12000;
+
+/// used to give events unique transaction ids
+static TXN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Deserialize)]
+pub struct MatrixConfig {
+    /// the base url of the homeserver, like https://matrix.org
+    homeserver: String,
+    /// the access token of the account that sends update messages
+    access_token: String,
+    /// the rooms to send update messages to, like !abcdef:matrix.org. the account must already be in them
+    room_ids: Vec<String>,
+    #[serde(flatten)]
+    attachments: AttachmentsConfig,
+}
+
+impl Debug for MatrixConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatrixConfig")
+            .field("homeserver", &self.homeserver)
+            .field("room_ids", &self.room_ids)
+            .finish()
+    }
+}
+
+fn txn_id() -> String {
+    format!(
+        "extension-version-watcher-{}-{}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis(),
+        TXN_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+impl Notifier for MatrixConfig {
+    #[tracing::instrument(name = "send_to_matrix", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(mut summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let (attachments, notes) = self
+            .attachments
+            .prepare(std::mem::take(&mut summary.attachments));
+        let message = report.render(MARKDOWN_TEMPLATE, &summary, &notes, false);
+
+        let client = Client::new();
+        // media is uploaded once and shared with every room
+        let mut uploaded = vec![];
+        for attachment in &attachments {
+            match self.upload(&client, attachment).await {
+                Ok(content_uri) => uploaded.push((attachment, content_uri)),
+                Err(error) => error!(
+                    filename = attachment.filename,
+                    "failed to upload attachment: {error:#}"
+                ),
+            }
+        }
+
+        for room_id in &self.room_ids {
+            async {
+                for chunk in split_message(&message, MAX_MESSAGE_LEN) {
+                    let content = json!({
+                        "msgtype": "m.text",
+                        "format": "org.matrix.custom.html",
+                        "formatted_body": to_html(&chunk),
+                        "body": chunk,
+                    });
+                    if let Err(error) = self.send_event(&client, room_id, content).await {
+                        error!("failed to send update message: {error:#}");
+                        return;
+                    }
+                }
+                info!("sent update message");
+                for (attachment, content_uri) in &uploaded {
+                    let content = json!({
+                        "msgtype": "m.file",
+                        "body": attachment.filename,
+                        "filename": attachment.filename,
+                        "url": content_uri,
+                        "info": {
+                            "mimetype": attachment.content_type(),
+                            "size": attachment.data.len(),
+                        },
+                    });
+                    if let Err(error) = self.send_event(&client, room_id, content).await {
+                        error!(
+                            filename = attachment.filename,
+                            "failed to send attachment: {error:#}"
+                        );
+                    }
+                }
+            }
+            .instrument(tracing::info_span!("room", room_id))
+            .await
+        }
+    }
+}
+
+impl MatrixConfig {
+    fn url(&self, path: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(&self.homeserver).context("invalid homeserver url")?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid homeserver url"))?
+            .pop_if_empty()
+            .extend(path);
+        Ok(url)
+    }
+
+    async fn request(&self, request: reqwest::RequestBuilder) -> Result<Value> {
+        let response = request
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .context("couldn't send request to the homeserver")?;
+        let status = response.status();
+        let body = response.bytes().await.unwrap_or_default();
+        if !status.is_success() {
+            bail!(
+                "homeserver responded with {status}: {}",
+                String::from_utf8_lossy(&body)
+            );
+        }
+        serde_json::from_slice(&body).context("homeserver responded with invalid json")
+    }
+
+    /// returns the mxc:// uri of the uploaded file
+    async fn upload(&self, client: &Client, attachment: &Attachment) -> Result<String> {
+        debug!(filename = attachment.filename, "uploading attachment");
+        let mut url = self.url(&["_matrix", "media", "v3", "upload"])?;
+        url.query_pairs_mut()
+            .append_pair("filename", &attachment.filename);
+        let body = self
+            .request(
+                client
+                    .post(url)
+                    .header("Content-Type", attachment.content_type())
+                    .body(attachment.data.clone()),
+            )
+            .await?;
+        match body["content_uri"].as_str() {
+            Some(content_uri) => Ok(content_uri.to_string()),
+            None => bail!("homeserver didn't respond with a content uri"),
+        }
+    }
+
+    async fn send_event(&self, client: &Client, room_id: &str, content: Value) -> Result<()> {
+        let url = self.url(&[
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            room_id,
+            "send",
+            "m.room.message",
+            &txn_id(),
+        ])?;
+        self.request(client.put(url).json(&content)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Debug;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, Instrument};
+
+use crate::attachments::{Attachment, AttachmentsConfig};
+use crate::notify::{split_message, summarize, Notifier, Report};
+use crate::render_template::SLACK_TEMPLATE;
+
+/// slack truncates messages that are longer than 40000 characters, but recommends keeping them under 4000
+const MAX_MESSAGE_LEN: usize = 4000;
+
+fn default_api_url() -> String {
+    "https://slack.com/api".into()
+}
+
+/// update messages can be sent with an incoming webhook, or with a bot that can also upload the diffs
+#[derive(Deserialize)]
+pub struct SlackConfig {
+    /// https://hooks.slack.com/services/...
+    webhook_url: Option<String>,
+    /// a bot token (xoxb-...) with the chat:write and files:write scopes
+    token: Option<String>,
+    /// channels the bot sends update messages to
+    #[serde(default)]
+    channel_ids: Vec<String>,
+    #[serde(default = "default_api_url")]
+    api_url: String,
+    #[serde(flatten)]
+    attachments: AttachmentsConfig,
+}
+
+impl Debug for SlackConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the webhook url and token are secret
+        f.debug_struct("SlackConfig")
+            .field("channel_ids", &self.channel_ids)
+            .finish()
+    }
+}
+
+/// slack's markdown ("mrkdwn") uses <, > and & for links and mentions
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl Notifier for SlackConfig {
+    #[tracing::instrument(name = "send_to_slack", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(mut summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let escape_all = |lines: &mut Vec<String>| {
+            for line in lines {
+                *line = escape(line);
+            }
+        };
+        escape_all(&mut summary.updates);
+        escape_all(&mut summary.errors);
+        escape_all(&mut summary.recovered);
+        for (_, lines) in &mut summary.sections {
+            escape_all(lines);
+        }
+
+        let client = Client::new();
+        if let Some(ref webhook_url) = self.webhook_url {
+            // incoming webhooks can't upload files
+            let notes = self.attachments.links(&summary.attachments);
+            let message = report.render(SLACK_TEMPLATE, &summary, &notes, false);
+            async {
+                for chunk in split_message(&message, MAX_MESSAGE_LEN) {
+                    if let Err(error) = execute_webhook(&client, webhook_url, &chunk).await {
+                        error!("failed to send update message: {error:#}");
+                        return;
+                    }
+                }
+                info!("sent update message");
+            }
+            .instrument(tracing::info_span!("webhook"))
+            .await
+        }
+
+        let Some(ref token) = self.token else {
+            return;
+        };
+        let (attachments, notes) = self
+            .attachments
+            .prepare(std::mem::take(&mut summary.attachments));
+        let message = report.render(SLACK_TEMPLATE, &summary, &notes, false);
+        for channel in &self.channel_ids {
+            async {
+                let mut thread_ts = None;
+                for chunk in split_message(&message, MAX_MESSAGE_LEN) {
+                    match self.post_message(&client, token, channel, &chunk).await {
+                        Ok(ts) => thread_ts = thread_ts.or(Some(ts)),
+                        Err(error) => {
+                            error!("failed to send update message: {error:#}");
+                            return;
+                        }
+                    }
+                }
+                info!("sent update message");
+                if attachments.is_empty() {
+                    return;
+                }
+                match self
+                    .upload_files(&client, token, channel, thread_ts.as_deref(), &attachments)
+                    .await
+                {
+                    Ok(_) => info!("uploaded attachments"),
+                    Err(error) => error!("failed to upload attachments: {error:#}"),
+                }
+            }
+            .instrument(tracing::info_span!("channel", channel))
+            .await
+        }
+    }
+}
+
+async fn execute_webhook(client: &Client, webhook_url: &str, text: &str) -> Result<()> {
+    let response = client
+        .post(webhook_url)
+        .json(&json!({ "text": text }))
+        .send()
+        .await
+        // the url is secret
+        .map_err(reqwest::Error::without_url)
+        .context("couldn't execute webhook")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("webhook responded with {status}: {body}");
+    }
+    Ok(())
+}
+
+/// checks the `ok` field that every slack web api response has
+async fn api_response(response: reqwest::Response) -> Result<Value> {
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .context("couldn't read slack's response")?;
+    let body: Value = serde_json::from_slice(&body)
+        .with_context(|| format!("slack responded with {status} and invalid json"))?;
+    if body["ok"] != true {
+        bail!(
+            "slack responded with an error: {}",
+            body["error"].as_str().unwrap_or("unknown")
+        );
+    }
+    Ok(body)
+}
+
+impl SlackConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.webhook_url.is_none() && self.token.is_none() {
+            bail!("slack notifiers must have a webhook_url or a token");
+        }
+        Ok(())
+    }
+
+    /// returns the timestamp of the message, which is used as its id
+    async fn post_message(
+        &self,
+        client: &Client,
+        token: &str,
+        channel: &str,
+        text: &str,
+    ) -> Result<String> {
+        let response = client
+            .post(format!("{}/chat.postMessage", self.api_url))
+            .bearer_auth(token)
+            .json(&json!({ "channel": channel, "text": text }))
+            .send()
+            .await
+            .context("couldn't post message")?;
+        let body = api_response(response).await?;
+        Ok(body["ts"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// uploads the files to slack and shares them in a thread on the update message
+    async fn upload_files(
+        &self,
+        client: &Client,
+        token: &str,
+        channel: &str,
+        thread_ts: Option<&str>,
+        attachments: &[Attachment],
+    ) -> Result<()> {
+        let mut files = vec![];
+        for attachment in attachments {
+            debug!(filename = attachment.filename, "uploading file");
+            let response = client
+                .post(format!("{}/files.getUploadURLExternal", self.api_url))
+                .bearer_auth(token)
+                .form(&[
+                    ("filename", attachment.filename.clone()),
+                    ("length", attachment.data.len().to_string()),
+                ])
+                .send()
+                .await
+                .context("couldn't get upload url")?;
+            let body = api_response(response).await?;
+            let (Some(upload_url), Some(file_id)) =
+                (body["upload_url"].as_str(), body["file_id"].as_str())
+            else {
+                bail!("slack didn't respond with an upload url");
+            };
+            let response = client
+                .post(upload_url)
+                .body(attachment.data.clone())
+                .send()
+                .await
+                .context("couldn't upload file")?;
+            if !response.status().is_success() {
+                bail!("uploading file failed with {}", response.status());
+            }
+            files.push(json!({ "id": file_id, "title": attachment.filename }));
+        }
+
+        let mut complete = json!({ "files": files, "channel_id": channel });
+        if let Some(thread_ts) = thread_ts {
+            complete["thread_ts"] = thread_ts.into();
+        }
+        let response = client
+            .post(format!("{}/files.completeUploadExternal", self.api_url))
+            .bearer_auth(token)
+            .json(&complete)
+            .send()
+            .await
+            .context("couldn't complete upload")?;
+        api_response(response).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Debug;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{error, info};
+
+use crate::attachments::AttachmentsConfig;
+use crate::notify::{summarize, Notifier, Report};
+use crate::render_template::MARKDOWN_TEMPLATE;
+
+/// teams rejects messages larger than about 28 KB, so cards are kept well under that
+const MAX_CARD_LEN: usize = 20000;
+
+#[derive(Deserialize)]
+pub struct TeamsConfig {
+    /// the url of an incoming webhook or a workflow that posts adaptive cards
+    webhook_url: String,
+    /// teams webhooks can't send files, so every attachment is linked to (see attachment_base_url)
+    #[serde(flatten)]
+    attachments: AttachmentsConfig,
+}
+
+impl Debug for TeamsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the webhook url is secret
+        f.debug_struct("TeamsConfig").finish_non_exhaustive()
+    }
+}
+
+fn text_block(text: &str) -> Value {
+    json!({ "type": "TextBlock", "text": text, "wrap": true })
+}
+
+fn card(body: Vec<Value>) -> Value {
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "msteams": { "width": "Full" },
+                "body": body,
+            },
+        }],
+    })
+}
+
+impl Notifier for TeamsConfig {
+    #[tracing::instrument(name = "send_to_teams", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let notes = self.attachments.links(&summary.attachments);
+        let message = report.render(MARKDOWN_TEMPLATE, &summary, &notes, false);
+
+        // every paragraph is a text block, and the blocks are split into as many cards as needed
+        let mut cards = vec![];
+        let mut body = vec![];
+        let mut len = 0;
+        for paragraph in message.split("\n\n") {
+            if !body.is_empty() && len + paragraph.len() > MAX_CARD_LEN {
+                cards.push(std::mem::take(&mut body));
+                len = 0;
+            }
+            body.push(text_block(
+                &paragraph.chars().take(MAX_CARD_LEN).collect::<String>(),
+            ));
+            len += paragraph.len();
+        }
+        cards.push(body);
+
+        let client = Client::new();
+        for body in cards {
+            if let Err(error) = execute_webhook(&client, &self.webhook_url, &card(body)).await {
+                error!("failed to send update message: {error:#}");
+                return;
+            }
+        }
+        info!("sent update message");
+    }
+}
+
+async fn execute_webhook(client: &Client, webhook_url: &str, card: &Value) -> Result<()> {
+    let response = client
+        .post(webhook_url)
+        .json(card)
+        .send()
+        .await
+        // the url is secret
+        .map_err(reqwest::Error::without_url)
+        .context("couldn't execute webhook")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("webhook responded with {status}: {body}");
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::{Debug, Display};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::attachments::{Attachment, AttachmentsConfig};
+use crate::notify::{inline_html, split_message, summarize, Notifier, Report};
+use crate::render_template::MARKDOWN_TEMPLATE;
+
+/// telegram's limit on the length of a message's text, after the html is parsed
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// the number of times a request is retried after being rate limited
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+fn default_api_url() -> String {
+    "https://api.telegram.org".into()
+}
+
+/// chats can be given by their id, or by the username of a public channel or group like "@example"
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Username(String),
+}
+
+impl Display for ChatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatId::Id(id) => write!(f, "{id}"),
+            ChatId::Username(username) => f.write_str(username),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TelegramConfig {
+    /// the token of the bot from @BotFather
+    token: String,
+    /// the chats to send update messages to. the bot must be a member of them
+    chat_ids: Vec<ChatId>,
+    #[serde(default = "default_api_url")]
+    api_url: String,
+    #[serde(flatten)]
+    attachments: AttachmentsConfig,
+}
+
+impl Debug for TelegramConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the token is secret
+        f.debug_struct("TelegramConfig")
+            .field("chat_ids", &self.chat_ids)
+            .finish()
+    }
+}
+
+/// converts a chunk of an update message to the html that telegram supports, which doesn't have line breaks
+fn to_telegram_html(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            format!(
+                "{}{}",
+                &line[..line.len() - trimmed.len()],
+                inline_html(trimmed)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Notifier for TelegramConfig {
+    #[tracing::instrument(name = "send_to_telegram", skip(report))]
+    async fn notify(&self, report: &Report<'_>) {
+        let Some(mut summary) = summarize(report) else {
+            info!("no updates or errors");
+            return;
+        };
+        let (attachments, notes) = self
+            .attachments
+            .prepare(std::mem::take(&mut summary.attachments));
+        let message = report.render(MARKDOWN_TEMPLATE, &summary, &notes, false);
+
+        let client = Client::new();
+        for chat_id in &self.chat_ids {
+            async {
+                // the diffs are sent as replies to the first message
+                let mut message_id = None;
+                // the message is split before it is converted, since the limit is on the text without the html tags
+                for chunk in split_message(&message, MAX_MESSAGE_LEN) {
+                    match self
+                        .send_message(&client, chat_id, &to_telegram_html(&chunk))
+                        .await
+                    {
+                        Ok(id) => message_id = message_id.or(id),
+                        Err(error) => {
+                            error!("failed to send update message: {error:#}");
+                            return;
+                        }
+                    }
+                }
+                info!("sent update message");
+                for attachment in &attachments {
+                    if let Err(error) = self
+                        .send_document(&client, chat_id, message_id, attachment)
+                        .await
+                    {
+                        error!(
+                            filename = attachment.filename,
+                            "failed to send attachment: {error:#}"
+                        );
+                    }
+                }
+            }
+            .instrument(tracing::info_span!("chat", %chat_id))
+            .await
+        }
+    }
+}
+
+impl TelegramConfig {
+    /// calls a method of the bot api, retrying if the bot is rate limited. form is called for every attempt, since
+    /// multipart forms can't be cloned
+    async fn request(
+        &self,
+        client: &Client,
+        method: &str,
+        form: impl Fn() -> Form,
+    ) -> Result<Value> {
+        let mut retries = 0;
+        loop {
+            let response = client
+                .post(format!("{}/bot{}/{method}", self.api_url, self.token))
+                .multipart(form())
+                .send()
+                .await
+                // the url contains the bot's token
+                .map_err(reqwest::Error::without_url)
+                .with_context(|| format!("couldn't call {method}"))?;
+            let status = response.status();
+            let body = response.bytes().await.unwrap_or_default();
+            let body: Value = serde_json::from_slice(&body)
+                .with_context(|| format!("telegram responded with {status} and invalid json"))?;
+            if status == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RATE_LIMIT_RETRIES {
+                let retry_after = body["parameters"]["retry_after"].as_u64().unwrap_or(1);
+                warn!(retry_after, "rate limited by telegram, retrying");
+                tokio::time::sleep(Duration::from_secs(retry_after.min(60))).await;
+                retries += 1;
+                continue;
+            }
+            if body["ok"] != true {
+                bail!(
+                    "telegram responded with {status}: {}",
+                    body["description"].as_str().unwrap_or("unknown error")
+                );
+            }
+            return Ok(body);
+        }
+    }
+
+    /// returns the id of the message
+    async fn send_message(
+        &self,
+        client: &Client,
+        chat_id: &ChatId,
+        html: &str,
+    ) -> Result<Option<i64>> {
+        let body = self
+            .request(client, "sendMessage", || {
+                Form::new()
+                    .text("chat_id", chat_id.to_string())
+                    .text("text", html.to_string())
+                    .text("parse_mode", "HTML")
+                    .text(
+                        "link_preview_options",
+                        json!({ "is_disabled": true }).to_string(),
+                    )
+            })
+            .await?;
+        Ok(body["result"]["message_id"].as_i64())
+    }
+
+    async fn send_document(
+        &self,
+        client: &Client,
+        chat_id: &ChatId,
+        reply_to: Option<i64>,
+        attachment: &Attachment,
+    ) -> Result<()> {
+        debug!(filename = attachment.filename, "sending attachment");
+        self.request(client, "sendDocument", || {
+            let mut form = Form::new().text("chat_id", chat_id.to_string()).part(
+                "document",
+                Part::bytes(attachment.data.clone()).file_name(attachment.filename.clone()),
+            );
+            if let Some(message_id) = reply_to {
+                form = form.text(
+                    "reply_parameters",
+                    json!({ "message_id": message_id, "allow_sending_without_reply": true })
+                        .to_string(),
+                );
+            }
+            form
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::notify::updated_extension;
+    use crate::test_server::{serve, Request};
+
+    /// the text of a message, without telegram's html
+    fn plain_text(html: &str) -> String {
+        regex::Regex::new("<[^>]*>")
+            .unwrap()
+            .replace_all(html, "")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&amp;", "&")
+    }
+
+    #[tokio::test]
+    async fn splits_long_messages() {
+        let (url, mut requests) = serve(r#"{"ok":true,"result":{"message_id":5}}"#).await;
+        let config: TelegramConfig = toml::from_str(&format!(
+            r#"
+            token = "123:abc"
+            chat_ids = [-100, "@channel"]
+            api_url = "{url}"
+            "#
+        ))
+        .unwrap();
+
+        let mut checked = (0..300)
+            .map(|i| updated_extension(&format!("extension-{i}"), None))
+            .collect::<Vec<_>>();
+        checked.push(updated_extension("diffed", Some("-old\n+new\n")));
+        config
+            .notify(&Report {
+                checked_extensions: checked.iter().collect(),
+                error_events: &HashMap::new(),
+                mention: None,
+                template: None,
+            })
+            .await;
+
+        let mut received = vec![];
+        while let Ok(request) = requests.try_recv() {
+            received.push(request);
+        }
+        for chat_id in ["-100", "@channel"] {
+            let chat_requests = received
+                .iter()
+                .filter(|r| r.field("chat_id").as_deref() == Some(chat_id))
+                .collect::<Vec<_>>();
+            let (messages, documents): (Vec<&Request>, Vec<_>) = chat_requests
+                .into_iter()
+                .partition(|r| r.path == "/bot123:abc/sendMessage");
+            assert!(messages.len() > 1);
+            let mut text = String::new();
+            for message in messages {
+                assert_eq!(message.field("parse_mode").as_deref(), Some("HTML"));
+                let chunk = plain_text(&message.field("text").unwrap());
+                assert!(chunk.chars().count() <= MAX_MESSAGE_LEN);
+                text.push_str(&chunk);
+                text.push('\n');
+            }
+            for i in 0..300 {
+                assert!(text.contains(&format!("EXTENSION-{i}: 1.0 -> 1.1\n")));
+            }
+
+            assert_eq!(documents.len(), 1);
+            assert_eq!(documents[0].path, "/bot123:abc/sendDocument");
+            assert!(documents[0].multipart().contains(&(
+                "document".into(),
+                Some("diffed-1.0-1.1.diff".into()),
+                "-old\n+new\n".into()
+            )));
+            let reply: Value =
+                serde_json::from_str(&documents[0].field("reply_parameters").unwrap()).unwrap();
+            assert_eq!(reply["message_id"], 5);
+        }
+    }
+}
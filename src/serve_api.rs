@@ -0,0 +1,339 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! a read-only json api of the extensions, their versions and diffs between them. see docs/api.md
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::compare_versions::{compare_versions, find_version};
+use crate::load_versions;
+use crate::post_webhook::update_payload;
+use crate::record_history::{load_history, HistoryEntry};
+use crate::serve_dashboard::{
+    extension_status, find_extension, known_versions, DiffQuery, ErrorResponse, ExtensionStatus,
+    ServerState,
+};
+
+/// errors are returned as `{"error": "..."}`
+pub struct ApiError(StatusCode, String);
+
+impl From<ErrorResponse> for ApiError {
+    fn from(error: ErrorResponse) -> Self {
+        ApiError(error.0, error.1)
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        ErrorResponse::from(error).into()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+async fn extensions(State(state): State<ServerState>) -> Result<Response, ApiError> {
+    let versions = load_versions().await?;
+    let history = load_history().await?;
+    let extensions = state
+        .extensions
+        .iter()
+        .map(|extension| extension_status(extension, &versions, &history))
+        .collect::<Vec<_>>();
+    Ok(Json(extensions).into_response())
+}
+
+#[derive(Serialize)]
+struct ExtensionDetails<'a> {
+    #[serde(flatten)]
+    status: ExtensionStatus<'a>,
+    /// newest first
+    updates: Vec<&'a HistoryEntry>,
+}
+
+async fn extension(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+) -> Result<Response, ApiError> {
+    let extension = find_extension(&state, &name)?;
+    let versions = load_versions().await?;
+    let history = load_history().await?;
+    Ok(Json(ExtensionDetails {
+        status: extension_status(extension, &versions, &history),
+        updates: history
+            .updates
+            .iter()
+            .rev()
+            .filter(|update| update.name == extension.name)
+            .collect(),
+    })
+    .into_response())
+}
+
+#[derive(Serialize)]
+struct VersionInfo<'a> {
+    version: &'a str,
+    current: bool,
+    /// unix timestamp of the update to this version, in seconds, if it was recorded
+    found_at: Option<u64>,
+}
+
+async fn versions(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+) -> Result<Response, ApiError> {
+    let extension = find_extension(&state, &name)?;
+    let versions = load_versions().await?;
+    let history = load_history().await?;
+    let current = versions.get(&extension.name).map(String::as_str);
+    let versions = known_versions(extension, &versions, &history)
+        .into_iter()
+        .map(|version| VersionInfo {
+            version,
+            current: current == Some(version),
+            found_at: history
+                .updates
+                .iter()
+                .rev()
+                .find(|update| update.name == extension.name && update.cur_version == version)
+                .map(|update| update.checked_at),
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(versions).into_response())
+}
+
+async fn diff(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Response, ApiError> {
+    let extension = find_extension(&state, &name)?;
+    let update = compare_versions(&state.config, extension, &query.from, &query.to)
+        .await
+        .map_err(|error| ApiError(StatusCode::NOT_FOUND, format!("{error:#}")))?;
+    Ok(Json(json!({
+        "name": extension.name,
+        "update": update_payload(&update),
+        "diff": update.diff,
+    }))
+    .into_response())
+}
+
+async fn file(
+    State(state): State<ServerState>,
+    Path((name, version, path)): Path<(String, String, String)>,
+) -> Result<Response, ApiError> {
+    let extension = find_extension(&state, &name)?;
+    if path
+        .split('/')
+        .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Err(ApiError(StatusCode::NOT_FOUND, "invalid path".into()));
+    }
+    find_version(&state.config, &extension.name, &version)
+        .await
+        .map_err(|error| ApiError(StatusCode::NOT_FOUND, format!("{error:#}")))?;
+    let data = tokio::fs::read(format!("./crx/{}-{version}/{path}", extension.name))
+        .await
+        .map_err(|_| ApiError(StatusCode::NOT_FOUND, format!("{path} doesn't exist")))?;
+    // files are never served as html or scripts, since they come from the extension
+    let content_type = if std::str::from_utf8(&data).is_ok() {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// compares the strings in constant time, so the token can't be guessed from response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn check(State(state): State<ServerState>, headers: HeaderMap) -> Result<Response, ApiError> {
+    let Some(ref token) = state.api_token else {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "checks can't be triggered without an api_token".into(),
+        ));
+    };
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| constant_time_eq(value.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return Err(ApiError(StatusCode::UNAUTHORIZED, "invalid token".into()));
+    }
+    info!("check triggered through the api");
+    // if a check is running, another one starts when it is done
+    state.check_now.notify_one();
+    Ok((StatusCode::ACCEPTED, Json(json!({ "status": "scheduled" }))).into_response())
+}
+
+pub fn api_routes() -> Router<ServerState> {
+    Router::new()
+        .route("/extensions", get(extensions))
+        .route("/extensions/:name", get(extension))
+        .route("/extensions/:name/versions", get(versions))
+        .route("/extensions/:name/diff", get(diff))
+        .route("/extensions/:name/versions/:version/files/*path", get(file))
+        .route("/check", post(check))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::Request;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::notify::updated_extension;
+
+    fn state(api_token: Option<&str>) -> ServerState {
+        ServerState {
+            config: Arc::new(toml::from_str("").unwrap()),
+            extensions: Arc::new(vec![updated_extension("foo", None).0]),
+            api_token: api_token.map(Into::into),
+            check_now: Arc::new(Notify::new()),
+        }
+    }
+
+    /// the status and body of a response from the api
+    async fn request(
+        state: ServerState,
+        request: Request<Body>,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = api_routes()
+            .with_state(state)
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn check(authorization: Option<&str>) -> Request<Body> {
+        let mut request = Request::post("/check");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_outside_crx() {
+        for (uri, error) in [
+            (
+                "/extensions/foo/versions/1.0/files/../../config.toml",
+                "invalid path",
+            ),
+            (
+                "/extensions/foo/versions/1.0/files/js/../../../config.toml",
+                "invalid path",
+            ),
+            (
+                "/extensions/foo/versions/1.0/files/js//background.js",
+                "invalid path",
+            ),
+            (
+                "/extensions/foo/versions/1.0/files/js/./background.js",
+                "invalid path",
+            ),
+            ("/extensions/foo/versions/1.0/files/js/", "invalid path"),
+            (
+                "/extensions/foo/versions/../files/config.toml",
+                "invalid version \"..\"",
+            ),
+            (
+                "/extensions/foo/versions/.hidden/files/a.js",
+                "invalid version \".hidden\"",
+            ),
+            (
+                "/extensions/foo/diff?from=..&to=1.0",
+                "invalid version \"..\"",
+            ),
+            ("/extensions/foo/diff?from=&to=1.0", "invalid version \"\""),
+            (
+                "/extensions/foo/diff?from=a%2F..%2F..&to=1.0",
+                "invalid version \"a/../..\"",
+            ),
+            (
+                "/extensions/bar/diff?from=1.0&to=1.1",
+                "unknown extension \"bar\"",
+            ),
+        ] {
+            let (status, body) = request(state(None), get(uri)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            assert_eq!(body["error"], error, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn check_needs_token() {
+        let (status, _) = request(state(None), check(Some("Bearer anything"))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        for authorization in [
+            None,
+            Some("Bearer wrong"),
+            Some("Bearer secret2"),
+            Some("Bearer "),
+            Some("secret"),
+            Some("Basic secret"),
+        ] {
+            let (status, body) = request(state(Some("secret")), check(authorization)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{authorization:?}");
+            assert_eq!(body["error"], "invalid token");
+        }
+
+        let state = state(Some("secret"));
+        let (status, body) = request(state.clone(), check(Some("Bearer secret"))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["status"], "scheduled");
+        // the permit is stored, so this doesn't wait
+        state.check_now.notified().await;
+    }
+}
@@ -0,0 +1,438 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! an optional web server with a dashboard of the extensions, their versions and diffs between them
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Context, Result};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use minijinja::{context, Environment};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+use crate::compare_versions::compare_versions;
+use crate::extensions::Extension;
+use crate::html_report::html_report;
+use crate::load_versions;
+use crate::record_history::{load_history, rfc3339, History, HistoryEntry};
+use crate::serve_api::api_routes;
+use crate::Config;
+
+fn default_address() -> String {
+    "127.0.0.1:8080".into()
+}
+
+const fn default_interval() -> u64 {
+    60
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct ServerConfig {
+    /// the address and port to listen on
+    #[serde(default = "default_address")]
+    address: String,
+    /// minutes between checks while the server is running
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    #[serde(default = "default_true")]
+    dashboard: bool,
+    /// if the json api should be served at /api
+    #[serde(default = "default_true")]
+    api: bool,
+    /// the bearer token needed to trigger checks through the api
+    pub api_token: Option<String>,
+}
+
+impl Debug for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the token is secret
+        f.debug_struct("ServerConfig")
+            .field("address", &self.address)
+            .field("dashboard", &self.dashboard)
+            .field("api", &self.api)
+            .finish_non_exhaustive()
+    }
+}
+
+/// what the server's handlers have access to
+#[derive(Clone)]
+pub struct ServerState {
+    pub config: Arc<Config>,
+    pub extensions: Arc<Vec<Extension>>,
+    pub api_token: Option<Arc<str>>,
+    /// notified to start a check before the interval is over
+    pub check_now: Arc<Notify>,
+}
+
+fn templates() -> &'static Environment<'static> {
+    static TEMPLATES: OnceLock<Environment<'static>> = OnceLock::new();
+    TEMPLATES.get_or_init(|| {
+        let mut env = Environment::new();
+        for (name, source) in [
+            (
+                "layout.html",
+                include_str!("../templates/dashboard/layout.html"),
+            ),
+            (
+                "index.html",
+                include_str!("../templates/dashboard/index.html"),
+            ),
+            (
+                "extension.html",
+                include_str!("../templates/dashboard/extension.html"),
+            ),
+        ] {
+            env.add_template(name, source)
+                .expect("dashboard templates are valid");
+        }
+        env
+    })
+}
+
+/// an error page. the error is logged if it is an internal error
+pub struct ErrorResponse(pub StatusCode, pub String);
+
+impl From<anyhow::Error> for ErrorResponse {
+    fn from(error: anyhow::Error) -> Self {
+        error!("{error:#}");
+        ErrorResponse(StatusCode::INTERNAL_SERVER_ERROR, format!("{error:#}"))
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+fn render(name: &str, context: minijinja::Value) -> Result<Html<String>, ErrorResponse> {
+    let template = templates().get_template(name).unwrap();
+    Ok(Html(
+        template
+            .render(context)
+            .context("couldn't render template")?,
+    ))
+}
+
+/// an extension with its current version and last check
+#[derive(Serialize)]
+pub struct ExtensionStatus<'a> {
+    pub name: &'a str,
+    pub display_name: &'a str,
+    pub id: &'a str,
+    pub tags: &'a [String],
+    pub version: Option<&'a str>,
+    /// unix timestamp, in seconds
+    pub checked_at: Option<u64>,
+    pub error: Option<&'a str>,
+    /// unix timestamp of the latest update, in seconds
+    pub last_update: Option<u64>,
+}
+
+pub fn extension_status<'a>(
+    extension: &'a Extension,
+    versions: &'a HashMap<String, String>,
+    history: &'a History,
+) -> ExtensionStatus<'a> {
+    let check = history.checks.get(&extension.name);
+    ExtensionStatus {
+        name: &extension.name,
+        display_name: &extension.display_name,
+        id: &extension.id,
+        tags: &extension.tags,
+        version: versions.get(&extension.name).map(String::as_str),
+        checked_at: check.map(|check| check.checked_at),
+        error: check.and_then(|check| check.error.as_deref()),
+        last_update: history
+            .updates
+            .iter()
+            .rev()
+            .find(|update| update.name == extension.name)
+            .map(|update| update.checked_at),
+    }
+}
+
+/// every version of the extension that is known, newest first
+pub fn known_versions<'a>(
+    extension: &Extension,
+    versions: &'a HashMap<String, String>,
+    history: &'a History,
+) -> Vec<&'a str> {
+    let mut known = vec![];
+    let updates = history
+        .updates
+        .iter()
+        .rev()
+        .filter(|update| update.name == extension.name);
+    let candidates = versions
+        .get(&extension.name)
+        .map(String::as_str)
+        .into_iter()
+        .chain(updates.flat_map(|update| [update.cur_version.as_str(), &update.prev_version]));
+    for version in candidates {
+        if version != "None" && !known.contains(&version) {
+            known.push(version);
+        }
+    }
+    known
+}
+
+pub fn find_extension<'a>(
+    state: &'a ServerState,
+    name: &str,
+) -> Result<&'a Extension, ErrorResponse> {
+    state
+        .extensions
+        .iter()
+        .find(|extension| extension.name == name)
+        .ok_or_else(|| ErrorResponse(StatusCode::NOT_FOUND, format!("unknown extension {name:?}")))
+}
+
+/// like "2023-09-14 18:03 UTC"
+fn display_time(secs: u64) -> String {
+    let time = rfc3339(secs);
+    format!("{} {} UTC", &time[..10], &time[11..16])
+}
+
+fn index_context(
+    extensions: &[Extension],
+    versions: &HashMap<String, String>,
+    history: &History,
+) -> minijinja::Value {
+    let extensions = extensions
+        .iter()
+        .map(|extension| {
+            let status = extension_status(extension, versions, history);
+            context! {
+                checked_at => status.checked_at.map(display_time),
+                last_update => status.last_update.map(display_time),
+                ..minijinja::Value::from_serialize(&status)
+            }
+        })
+        .collect::<Vec<_>>();
+    context! { extensions, version => env!("CARGO_PKG_VERSION") }
+}
+
+async fn index(State(state): State<ServerState>) -> Result<Html<String>, ErrorResponse> {
+    let versions = load_versions().await?;
+    let history = load_history().await?;
+    render(
+        "index.html",
+        index_context(&state.extensions, &versions, &history),
+    )
+}
+
+fn extension_context(
+    extension: &Extension,
+    versions: &HashMap<String, String>,
+    history: &History,
+) -> minijinja::Value {
+    let status = extension_status(extension, versions, history);
+    let updates = history
+        .updates
+        .iter()
+        .rev()
+        .filter(|update| update.name == extension.name)
+        .map(|update: &HistoryEntry| {
+            context! {
+                date => display_time(update.checked_at),
+                ..minijinja::Value::from_serialize(update)
+            }
+        })
+        .collect::<Vec<_>>();
+    context! {
+        extension => context! {
+            checked_at => status.checked_at.map(display_time),
+            ..minijinja::Value::from_serialize(&status)
+        },
+        versions => known_versions(extension, versions, history),
+        updates,
+        version => env!("CARGO_PKG_VERSION"),
+    }
+}
+
+async fn extension_page(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+) -> Result<Html<String>, ErrorResponse> {
+    let extension = find_extension(&state, &name)?;
+    let versions = load_versions().await?;
+    let history = load_history().await?;
+    render(
+        "extension.html",
+        extension_context(extension, &versions, &history),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    pub from: String,
+    pub to: String,
+}
+
+async fn diff_page(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Html<String>, ErrorResponse> {
+    let extension = find_extension(&state, &name)?;
+    let update = compare_versions(&state.config, extension, &query.from, &query.to)
+        .await
+        .map_err(|error| ErrorResponse(StatusCode::NOT_FOUND, format!("{error:#}")))?;
+    Ok(Html(
+        html_report(extension, &update).expect("compared versions have a diff"),
+    ))
+}
+
+/// serves a file from ./diff or ./feed
+async fn static_file(dir: &str, path: &str) -> Result<Response, ErrorResponse> {
+    if path
+        .split('/')
+        .any(|part| part.is_empty() || part.starts_with('.'))
+    {
+        return Err(ErrorResponse(StatusCode::NOT_FOUND, "not found".into()));
+    }
+    let data = tokio::fs::read(format!("./{dir}/{path}"))
+        .await
+        .map_err(|_| ErrorResponse(StatusCode::NOT_FOUND, "not found".into()))?;
+    let content_type = match path.rsplit_once('.').map_or("", |(_, extension)| extension) {
+        "html" => "text/html; charset=utf-8",
+        "diff" | "md" | "txt" => "text/plain; charset=utf-8",
+        "atom" => "application/atom+xml",
+        "json" => "application/json",
+        "gz" => "application/gzip",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], data).into_response())
+}
+
+async fn diff_file(Path(path): Path<String>) -> Result<Response, ErrorResponse> {
+    static_file("diff", &path).await
+}
+
+async fn feed_file(Path(path): Path<String>) -> Result<Response, ErrorResponse> {
+    static_file("feed", &path).await
+}
+
+pub fn dashboard_routes() -> Router<ServerState> {
+    Router::new()
+        .route("/", get(index))
+        .route("/extensions/:name", get(extension_page))
+        .route("/extensions/:name/diff", get(diff_page))
+        .route("/diff/*path", get(diff_file))
+        .route("/feed/*path", get(feed_file))
+}
+
+/// serves the dashboard and the api until the program exits
+#[tracing::instrument(skip(state))]
+pub async fn run_server(config: &ServerConfig, state: ServerState) -> Result<()> {
+    let mut router = Router::new();
+    if config.dashboard {
+        router = router.merge(dashboard_routes());
+    }
+    if config.api {
+        router = router.nest("/api", api_routes());
+    }
+    let listener = tokio::net::TcpListener::bind(&config.address)
+        .await
+        .with_context(|| format!("couldn't listen on {}", config.address))?;
+    info!("serving");
+    axum::serve(listener, router.with_state(state))
+        .await
+        .context("server failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::updated_extension;
+    use crate::record_history::{history_entry, LastCheck};
+
+    #[test]
+    fn pages_are_escaped() {
+        let (mut extension, _) = updated_extension("evil", None);
+        extension.display_name = "<script>alert(1)</script>".into();
+        let mut entry = history_entry("evil", 1694714580);
+        entry.display_name = extension.display_name.clone();
+        entry.added_permissions = vec!["<img src=x onerror=alert(2)>".into()];
+        let history = History {
+            updates: vec![entry],
+            checks: [(
+                "evil".to_string(),
+                LastCheck {
+                    checked_at: 1694714580,
+                    error: Some("<b>couldn't download</b>".into()),
+                },
+            )]
+            .into(),
+        };
+        let versions = HashMap::from([("evil".to_string(), "1.1".to_string())]);
+
+        let Html(index) = render(
+            "index.html",
+            index_context(&[extension.clone()], &versions, &history),
+        )
+        .unwrap_or_else(|_| panic!("couldn't render index.html"));
+        let Html(page) = render(
+            "extension.html",
+            extension_context(&extension, &versions, &history),
+        )
+        .unwrap_or_else(|_| panic!("couldn't render extension.html"));
+        for html in [&index, &page] {
+            assert!(!html.contains("<script>alert"));
+            assert!(html.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"));
+            assert!(!html.contains("<b>"));
+            assert!(html.contains("2023-09-14 18:03 UTC"));
+        }
+        assert!(!page.contains("<img"));
+        assert!(page.contains("&lt;img src=x onerror=alert(2)&gt;"));
+        assert!(page.contains("<code>1.0</code> -&gt; <code>1.1</code>"));
+    }
+
+    #[tokio::test]
+    async fn static_files_stay_in_their_dir() {
+        for path in [
+            "",
+            "../config.toml",
+            "a/../../config.toml",
+            ".hidden",
+            "a/.git/config",
+            "a//b.diff",
+            "a/",
+            "/etc/passwd",
+        ] {
+            let Err(ErrorResponse(status, _)) = static_file("diff", path).await else {
+                panic!("{path:?} was served");
+            };
+            assert_eq!(status, StatusCode::NOT_FOUND, "{path:?}");
+        }
+    }
+}
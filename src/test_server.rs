@@ -0,0 +1,183 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! a tiny http server for tests, which records every request it gets and answers them all with the same response
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// the path and query
+    pub path: String,
+    /// names are lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("request body is not json")
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// splits a multipart body into (name, filename, data) parts
+    pub fn multipart(&self) -> Vec<(String, Option<String>, String)> {
+        let boundary = self
+            .header("content-type")
+            .and_then(|c| c.split_once("boundary="))
+            .expect("request body is not multipart")
+            .1;
+        self.text()
+            .split(&format!("--{boundary}"))
+            .filter_map(|part| {
+                let (headers, data) = part.split_once("\r\n\r\n")?;
+                let disposition = headers
+                    .lines()
+                    .find(|l| l.to_ascii_lowercase().starts_with("content-disposition"))?;
+                let field = |key: &str| {
+                    disposition
+                        .split(&format!("{key}=\""))
+                        .nth(1)
+                        .map(|rest| rest.split('"').next().unwrap().to_string())
+                };
+                Some((
+                    field("name")?,
+                    field("filename"),
+                    data.strip_suffix("\r\n").unwrap_or(data).to_string(),
+                ))
+            })
+            .collect()
+    }
+
+    /// the data of the multipart field with the given name
+    pub fn field(&self, name: &str) -> Option<String> {
+        self.multipart()
+            .into_iter()
+            .find(|(n, _, _)| n == name)
+            .map(|(_, _, data)| data)
+    }
+}
+
+/// listens on a random local port. returns the server's base url, like `http://127.0.0.1:1234`
+pub async fn serve(response: &'static str) -> (String, mpsc::UnboundedReceiver<Request>) {
+    serve_statuses(&[200], response).await
+}
+
+/// like `serve`, but the nth request is answered with the nth status. the last status is repeated
+pub async fn serve_statuses(
+    statuses: &'static [u16],
+    response: &'static str,
+) -> (String, mpsc::UnboundedReceiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let count = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let sender = sender.clone();
+            let count = count.clone();
+            tokio::spawn(async move { handle(stream, sender, statuses, &count, response).await });
+        }
+    });
+    (url, receiver)
+}
+
+async fn handle(
+    stream: TcpStream,
+    sender: mpsc::UnboundedSender<Request>,
+    statuses: &[u16],
+    count: &AtomicUsize,
+    response: &str,
+) {
+    let mut stream = BufReader::new(stream);
+    // connections are kept alive, so there can be more than one request
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        let mut request = Request {
+            method,
+            path,
+            headers,
+            body: vec![],
+        };
+        if let Some(len) = request.header("content-length") {
+            request.body = vec![0; len.parse().unwrap()];
+            stream.read_exact(&mut request.body).await.unwrap();
+        } else if request.header("transfer-encoding") == Some("chunked") {
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let len = usize::from_str_radix(line.trim(), 16).unwrap();
+                let mut chunk = vec![0; len + 2];
+                stream.read_exact(&mut chunk).await.unwrap();
+                if len == 0 {
+                    break;
+                }
+                request.body.extend_from_slice(&chunk[..len]);
+            }
+        }
+        sender.send(request).unwrap();
+
+        let i = count.fetch_add(1, Ordering::SeqCst);
+        let status = statuses[i.min(statuses.len() - 1)];
+        let reply = format!(
+            "HTTP/1.1 {status} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{response}",
+            reqwest::StatusCode::from_u16(status)
+                .unwrap()
+                .canonical_reason()
+                .unwrap_or_default(),
+            response.len()
+        );
+        stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+    }
+}
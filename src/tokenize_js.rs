@@ -0,0 +1,422 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! a forgiving javascript tokenizer. it never fails: anything it doesn't understand becomes a single character punctuator.
+//! it is only meant for reformatting and analyzing code, not for validating it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// identifiers and keywords
+    Word,
+    Number,
+    String,
+    Template,
+    Regex,
+    Punct,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// byte offset of the token in the source
+    pub start: usize,
+    /// if there was a line break between this token and the previous one
+    pub newline_before: bool,
+}
+
+impl Token<'_> {
+    pub fn is(&self, text: &str) -> bool {
+        self.text == text
+    }
+
+    pub fn is_comment(&self) -> bool {
+        matches!(self.kind, TokenKind::LineComment | TokenKind::BlockComment)
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        self.kind == TokenKind::Word && KEYWORDS.contains(&self.text)
+    }
+
+    /// if this token can be the last token of an expression, which means a following `/` is division and a following `(` is a call
+    pub fn ends_value(&self) -> bool {
+        match self.kind {
+            TokenKind::Word => !self.is_keyword() || VALUE_KEYWORDS.contains(&self.text),
+            TokenKind::Number | TokenKind::String | TokenKind::Template | TokenKind::Regex => true,
+            TokenKind::Punct => matches!(self.text, ")" | "]" | "}"),
+            TokenKind::LineComment | TokenKind::BlockComment => false,
+        }
+    }
+}
+
+pub const KEYWORDS: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "of",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// keywords that are values on their own
+const VALUE_KEYWORDS: &[&str] = &["false", "null", "super", "this", "true"];
+
+/// punctuators, longest first so the first match is the longest match
+const PUNCTUATORS: &[&str] = &[
+    ">>>=", "...", "===", "!==", "**=", "<<=", ">>=", ">>>", "&&=", "||=", "??=", "=>", "==", "!=",
+    "<=", ">=", "&&", "||", "??", "?.", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+    "**", "<<", ">>",
+];
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b == b'\\' || b >= 0x80
+}
+
+pub fn tokenize_js(source: &str) -> Vec<Token<'_>> {
+    let bytes = source.as_bytes();
+    let mut tokens: Vec<Token> = vec![];
+    let mut i = 0;
+    let mut newline_before = false;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b.is_ascii_whitespace() {
+            newline_before |= b == b'\n' || b == b'\r';
+            i += 1;
+            continue;
+        }
+        // non breaking spaces, byte order marks and line/paragraph separators are whitespace too
+        if b >= 0x80 {
+            let c = source[i..].chars().next().unwrap();
+            if matches!(c, '\u{a0}' | '\u{feff}' | '\u{2028}' | '\u{2029}') {
+                newline_before |= matches!(c, '\u{2028}' | '\u{2029}');
+                i += c.len_utf8();
+                continue;
+            }
+        }
+
+        let start = i;
+        let kind = match b {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = skip_line_comment(bytes, i);
+                TokenKind::LineComment
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                TokenKind::BlockComment
+            }
+            b'/' if regex_allowed(tokens.iter().rev().find(|t| !t.is_comment())) => {
+                match skip_regex(bytes, i) {
+                    Some(end) => {
+                        i = end;
+                        TokenKind::Regex
+                    }
+                    None => {
+                        i += 1;
+                        TokenKind::Punct
+                    }
+                }
+            }
+            b'\'' | b'"' => {
+                i = skip_string(bytes, i);
+                TokenKind::String
+            }
+            b'`' => {
+                i = skip_template(bytes, i);
+                TokenKind::Template
+            }
+            b'0'..=b'9' => {
+                i = skip_number(bytes, i);
+                TokenKind::Number
+            }
+            b'.' if bytes.get(i + 1).is_some_and(|b| b.is_ascii_digit()) => {
+                i = skip_number(bytes, i);
+                TokenKind::Number
+            }
+            b'#' if bytes.get(i + 1).is_some_and(|&b| is_word_byte(b)) => {
+                i += 1;
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                TokenKind::Word
+            }
+            _ if is_word_byte(b) => {
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                TokenKind::Word
+            }
+            _ => {
+                let rest = &bytes[i..];
+                let len = PUNCTUATORS
+                    .iter()
+                    .find(|p| {
+                        rest.starts_with(p.as_bytes())
+                            // `a?.5:b` is a ternary, not optional chaining
+                            && !(**p == "?." && rest.get(2).is_some_and(|b| b.is_ascii_digit()))
+                    })
+                    .map(|p| p.len())
+                    .unwrap_or(1);
+                i += len;
+                TokenKind::Punct
+            }
+        };
+
+        // never split a multi byte character
+        i = i.min(bytes.len());
+        while !source.is_char_boundary(i) {
+            i += 1;
+        }
+        tokens.push(Token {
+            kind,
+            text: &source[start..i],
+            start,
+            newline_before,
+        });
+        newline_before = false;
+    }
+
+    tokens
+}
+
+fn regex_allowed(prev: Option<&Token>) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => match prev.kind {
+            TokenKind::Word => prev.is_keyword() && !VALUE_KEYWORDS.contains(&prev.text),
+            TokenKind::Punct => !matches!(prev.text, ")" | "]" | "}"),
+            _ => false,
+        },
+    }
+}
+
+fn skip_line_comment(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i] != b'\n' && bytes[i] != b'\r' {
+        i += 1;
+    }
+    i
+}
+
+fn skip_block_comment(bytes: &[u8], i: usize) -> usize {
+    match bytes[i + 2..].windows(2).position(|w| w == b"*/") {
+        Some(pos) => i + 2 + pos + 2,
+        None => bytes.len(),
+    }
+}
+
+fn skip_string(bytes: &[u8], mut i: usize) -> usize {
+    let quote = bytes[i];
+    i += 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => return i,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn skip_template(bytes: &[u8], mut i: usize) -> usize {
+    i += 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => return i + 1,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => i = skip_template_expression(bytes, i + 2),
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// skips the inside of a `${}` in a template, returning the index after the closing brace
+fn skip_template_expression(bytes: &[u8], mut i: usize) -> usize {
+    let mut depth = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' => i = skip_string(bytes, i),
+            b'`' => i = skip_template(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = skip_line_comment(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_block_comment(bytes, i),
+            b'{' => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' => {
+                depth -= 1;
+                i += 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// returns None if this isn't actually a regex literal (for example, if there is no closing slash on the same line)
+fn skip_regex(bytes: &[u8], mut i: usize) -> Option<usize> {
+    i += 1;
+    let mut in_class = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' | b'\r' => return None,
+            b'[' => {
+                in_class = true;
+                i += 1;
+            }
+            b']' => {
+                in_class = false;
+                i += 1;
+            }
+            b'/' if !in_class => {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                return Some(i);
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn skip_number(bytes: &[u8], mut i: usize) -> usize {
+    let hex = bytes[i] == b'0' && matches!(bytes.get(i + 1), Some(b'x' | b'X'));
+    while i < bytes.len() {
+        match bytes[i] {
+            b'e' | b'E' if !hex && matches!(bytes.get(i + 1), Some(b'+' | b'-')) => i += 2,
+            b if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' => i += 1,
+            _ => break,
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(TokenKind, &str)> {
+        tokenize_js(source)
+            .into_iter()
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn regex_or_division() {
+        assert_eq!(
+            kinds("a = b / c / d"),
+            [
+                (TokenKind::Word, "a"),
+                (TokenKind::Punct, "="),
+                (TokenKind::Word, "b"),
+                (TokenKind::Punct, "/"),
+                (TokenKind::Word, "c"),
+                (TokenKind::Punct, "/"),
+                (TokenKind::Word, "d"),
+            ]
+        );
+        assert_eq!(
+            kinds("x = /a\\/[/]b/g.test(y)")[2],
+            (TokenKind::Regex, "/a\\/[/]b/g")
+        );
+        assert_eq!(kinds("return /x/")[1], (TokenKind::Regex, "/x/"));
+        assert_eq!(kinds("(a) / 2")[3], (TokenKind::Punct, "/"));
+    }
+
+    #[test]
+    fn comments() {
+        let tokens = tokenize_js("a // one\n/* two\n */ b");
+        assert_eq!(tokens[1].kind, TokenKind::LineComment);
+        assert_eq!(tokens[1].text, "// one");
+        assert_eq!(tokens[2].kind, TokenKind::BlockComment);
+        assert_eq!(tokens[2].text, "/* two\n */");
+        assert!(tokens[2].newline_before);
+        assert_eq!(tokens[3].text, "b");
+    }
+
+    #[test]
+    fn template_literals() {
+        assert_eq!(
+            kinds("`a ${b + `c ${d}`} }` + e"),
+            [
+                (TokenKind::Template, "`a ${b + `c ${d}`} }`"),
+                (TokenKind::Punct, "+"),
+                (TokenKind::Word, "e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii() {
+        let source = "const été = \"ça\"; // 日本語\nf(été)";
+        let tokens = tokenize_js(source);
+        assert_eq!(tokens[1].text, "été");
+        assert_eq!(tokens[3].kind, TokenKind::String);
+        assert_eq!(tokens[3].text, "\"ça\"");
+        assert_eq!(tokens[5].text, "// 日本語");
+        for token in tokens {
+            assert_eq!(
+                &source[token.start..token.start + token.text.len()],
+                token.text
+            );
+        }
+    }
+}
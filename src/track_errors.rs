@@ -0,0 +1,295 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::notify::CheckedExtension;
+
+const ERRORS_PATH: &str = "./errors.toml";
+const ERRORS_HEADER: &str = r#"# error state file for extension-version-watcher
+# this file is used to track which extensions are failing to be checked, so that errors are only announced when they start.
+# you should not modify this file. if you delete it, every ongoing error will be announced again
+
+"#;
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorsConfig {
+    /// hours after which an ongoing error is announced again. if this isn't set, it is only announced once
+    remind_after: Option<u64>,
+    /// the number of consecutive failed checks after which an error is escalated
+    escalate_after: Option<u32>,
+    /// added to update messages with escalated errors, like a role mention. can be overridden per notifier
+    pub mention: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ErrorState {
+    /// unix timestamp of the first failed check, in seconds
+    pub since: u64,
+    /// unix timestamp of the last time the error was announced, in seconds
+    pub last_announced: u64,
+    /// the number of consecutive failed checks
+    pub failures: u32,
+    pub escalated: bool,
+}
+
+/// what happened to an extension's error state in this run
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorEvent {
+    /// the extension started failing
+    Started(ErrorState),
+    /// the extension is still failing, and the error isn't announced again
+    Ongoing(ErrorState),
+    /// the extension is still failing, and the error is announced again
+    Reminder(ErrorState),
+    /// the extension has failed escalate_after checks in a row
+    Escalated(ErrorState),
+    /// the extension was failing and was checked successfully
+    Recovered(ErrorState),
+}
+
+impl ErrorEvent {
+    /// if the error should be included in update messages
+    pub fn is_announced(&self) -> bool {
+        !matches!(self, ErrorEvent::Ongoing(_))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// like "3 hours" or "2 days"
+fn format_duration(secs: u64) -> String {
+    let (count, unit) = match secs {
+        0..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!("{count} {unit}{}", if count == 1 { "" } else { "s" })
+}
+
+/// describes how long an extension has been failing, like "failing for 3 hours, 5 checks in a row"
+pub fn describe(state: &ErrorState) -> String {
+    format!(
+        "failing for {}, {} checks in a row",
+        format_duration(now().saturating_sub(state.since)),
+        state.failures
+    )
+}
+
+/// updates errors.toml with the results of this run, and returns what happened to each extension that is or was
+/// failing, by name
+#[tracing::instrument(skip_all)]
+pub async fn track_errors(
+    config: &ErrorsConfig,
+    checked_extensions: &[CheckedExtension],
+) -> Result<HashMap<String, ErrorEvent>> {
+    let mut states: BTreeMap<String, ErrorState> =
+        match tokio::fs::read_to_string(ERRORS_PATH).await {
+            Ok(states) => toml::from_str(&states).context("failed to deserialize errors.toml")?,
+            Err(error) => {
+                debug!(%error, "failed to read errors.toml, no extensions were failing");
+                BTreeMap::new()
+            }
+        };
+
+    let events = update_states(config, &mut states, checked_extensions, now());
+    if let Err(error) = tokio::fs::write(
+        ERRORS_PATH,
+        format!("{ERRORS_HEADER}{}", toml::to_string(&states).unwrap()),
+    )
+    .await
+    {
+        warn!(%error, "failed to write errors.toml");
+    }
+    Ok(events)
+}
+
+/// updates the error states with the results of a run at now, and returns what happened to each extension that is or
+/// was failing
+fn update_states(
+    config: &ErrorsConfig,
+    states: &mut BTreeMap<String, ErrorState>,
+    checked_extensions: &[CheckedExtension],
+    now: u64,
+) -> HashMap<String, ErrorEvent> {
+    let mut events = HashMap::new();
+    for (extension, update) in checked_extensions {
+        let event = match (update, states.get_mut(&extension.name)) {
+            (Ok(_), None) => continue,
+            (Ok(_), Some(_)) => ErrorEvent::Recovered(states.remove(&extension.name).unwrap()),
+            (Err(_), None) => {
+                let state = ErrorState {
+                    since: now,
+                    last_announced: now,
+                    failures: 1,
+                    escalated: config.escalate_after.is_some_and(|n| n <= 1),
+                };
+                states.insert(extension.name.clone(), state);
+                if state.escalated {
+                    ErrorEvent::Escalated(state)
+                } else {
+                    ErrorEvent::Started(state)
+                }
+            }
+            (Err(_), Some(state)) => {
+                state.failures += 1;
+                if !state.escalated
+                    && config
+                        .escalate_after
+                        .is_some_and(|escalate_after| state.failures >= escalate_after)
+                {
+                    state.escalated = true;
+                    state.last_announced = now;
+                    ErrorEvent::Escalated(*state)
+                } else if config.remind_after.is_some_and(|remind_after| {
+                    now.saturating_sub(state.last_announced) >= remind_after * 3600
+                }) {
+                    state.last_announced = now;
+                    ErrorEvent::Reminder(*state)
+                } else {
+                    ErrorEvent::Ongoing(*state)
+                }
+            }
+        };
+        debug!(extension = extension.name, ?event);
+        events.insert(extension.name.clone(), event);
+    }
+
+    // extensions that are no longer checked can't recover
+    states.retain(|name, _| checked_extensions.iter().any(|(e, _)| &e.name == name));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::updated_extension;
+
+    fn failed(name: &str) -> CheckedExtension {
+        let (extension, _) = updated_extension(name, None);
+        (extension, Err(anyhow::anyhow!("couldn't download")))
+    }
+
+    fn config(toml: &str) -> ErrorsConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn error_lifecycle() {
+        let config = config("remind_after = 2\nescalate_after = 4");
+        let mut states = BTreeMap::new();
+        let mut run = |checked: CheckedExtension, now| {
+            update_states(&config, &mut states, &[checked], now).remove("foo")
+        };
+
+        let Some(ErrorEvent::Started(state)) = run(failed("foo"), 0) else {
+            panic!("the first failure should start an error");
+        };
+        assert_eq!((state.since, state.failures), (0, 1));
+        assert!(matches!(
+            run(failed("foo"), 3600),
+            Some(ErrorEvent::Ongoing(ErrorState { failures: 2, .. }))
+        ));
+        // remind_after hours after the error was announced
+        assert!(matches!(
+            run(failed("foo"), 7200),
+            Some(ErrorEvent::Reminder(ErrorState {
+                failures: 3,
+                last_announced: 7200,
+                ..
+            }))
+        ));
+        // escalate_after failures in a row
+        assert!(matches!(
+            run(failed("foo"), 7300),
+            Some(ErrorEvent::Escalated(ErrorState {
+                failures: 4,
+                escalated: true,
+                ..
+            }))
+        ));
+        // errors are only escalated once, and escalating counts as announcing
+        assert!(matches!(
+            run(failed("foo"), 7400),
+            Some(ErrorEvent::Ongoing(ErrorState { failures: 5, .. }))
+        ));
+        assert!(matches!(
+            run(failed("foo"), 7300 + 7200),
+            Some(ErrorEvent::Reminder(ErrorState { failures: 6, .. }))
+        ));
+
+        assert!(matches!(
+            run(updated_extension("foo", None), 20000),
+            Some(ErrorEvent::Recovered(ErrorState {
+                since: 0,
+                failures: 6,
+                ..
+            }))
+        ));
+        assert!(run(updated_extension("foo", None), 30000).is_none());
+        let Some(ErrorEvent::Started(state)) = run(failed("foo"), 40000) else {
+            panic!("failing again should start a new error");
+        };
+        assert_eq!((state.since, state.failures), (40000, 1));
+    }
+
+    #[test]
+    fn without_reminders() {
+        let config = config("");
+        let mut states = BTreeMap::new();
+        let checked = [failed("foo"), failed("bar")];
+        update_states(&config, &mut states, &checked, 0);
+        for now in [3600, 7 * 24 * 3600, 365 * 24 * 3600] {
+            let events = update_states(&config, &mut states, &checked, now);
+            assert!(events.values().all(|e| matches!(e, ErrorEvent::Ongoing(_))));
+        }
+
+        // extensions that are no longer checked are forgotten
+        update_states(&config, &mut states, &checked[..1], 0);
+        assert_eq!(states.keys().collect::<Vec<_>>(), ["foo"]);
+    }
+
+    #[test]
+    fn escalate_on_first_failure() {
+        let mut states = BTreeMap::new();
+        let events = update_states(
+            &config("escalate_after = 1"),
+            &mut states,
+            &[failed("foo")],
+            0,
+        );
+        assert!(matches!(
+            events["foo"],
+            ErrorEvent::Escalated(ErrorState {
+                failures: 1,
+                escalated: true,
+                ..
+            })
+        ));
+    }
+}
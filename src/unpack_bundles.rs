@@ -0,0 +1,444 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! reconstructs the original sources of bundled javascript into `./crx/{name}-{version}-unpacked`, so that it can be
+//! diffed alongside the extracted files. sources are taken from source maps when they include the original sources
+//! (`sources/`). otherwise, webpack style module registries are split into one file per module id (`modules/`).
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use globset::GlobSet;
+use serde::Deserialize;
+use tracing::{debug, trace, warn};
+use walkdir::WalkDir;
+
+use crate::default_true;
+use crate::file_changes::relative_path;
+use crate::tokenize_js::{tokenize_js, Token, TokenKind};
+
+/// registries with fewer modules than this are most likely just objects or arrays of functions
+const MIN_MODULES: usize = 2;
+
+/// module functions are called with (module, exports, require), so they never have more parameters than this
+const MAX_MODULE_PARAMS: usize = 3;
+
+#[derive(Debug, Deserialize)]
+pub struct UnpackConfig {
+    #[serde(default = "default_true")]
+    source_maps: bool,
+    #[serde(default = "default_true")]
+    modules: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SourceMap {
+    #[serde(default)]
+    source_root: Option<String>,
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    sources_content: Vec<Option<String>>,
+}
+
+pub fn unpacked_dir(name: &str, version: &str) -> String {
+    format!("./crx/{name}-{version}-unpacked")
+}
+
+/// unpacks the bundles in `./crx/{name}-{version}`, replacing any previously unpacked files.
+/// the unpacked directory is always created, even if nothing was unpacked, so that it can be diffed
+#[tracing::instrument(skip(config, ignore))]
+pub async fn unpack_bundles(
+    config: &UnpackConfig,
+    ignore: &GlobSet,
+    name: &str,
+    version: &str,
+) -> Result<()> {
+    let dir = PathBuf::from(format!("./crx/{name}-{version}"));
+    let out_dir = PathBuf::from(unpacked_dir(name, version));
+    if tokio::fs::try_exists(&out_dir)
+        .await
+        .context("couldn't check if unpacked dir exists")?
+    {
+        tokio::fs::remove_dir_all(&out_dir)
+            .await
+            .context("couldn't remove previously unpacked dir")?;
+    }
+    tokio::fs::create_dir_all(&out_dir)
+        .await
+        .context("couldn't create unpacked dir")?;
+
+    let (source_maps, modules, ignore) = (config.source_maps, config.modules, ignore.clone());
+    let files =
+        tokio::task::spawn_blocking(move || unpack_dir(&dir, &ignore, source_maps, modules))
+            .await
+            .unwrap();
+    debug!(files = files.len(), "unpacked bundles");
+
+    for (path, contents) in files {
+        let path = out_dir.join(path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("couldn't create {}", parent.display()))?;
+        }
+        tokio::fs::write(&path, contents)
+            .await
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// returns the unpacked files, relative to the unpacked directory
+fn unpack_dir(
+    dir: &Path,
+    ignore: &GlobSet,
+    source_maps: bool,
+    modules: bool,
+) -> BTreeMap<PathBuf, String> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let path = entry.path();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !matches!(extension.as_str(), "js" | "mjs" | "cjs" | "css") {
+            continue;
+        }
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        if ignore.is_match(relative_path(path, dir)) {
+            continue;
+        }
+
+        if source_maps {
+            match find_source_map(path, &contents) {
+                Ok(Some(map)) if map.sources_content.iter().any(Option::is_some) => {
+                    trace!(file = %relative.display(), "unpacking source map");
+                    add_sources(&map, &mut files);
+                    continue;
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    warn!(file = %relative.display(), "failed to read source map: {error:#}")
+                }
+            }
+        }
+
+        if modules && extension != "css" {
+            let found = split_modules(&contents);
+            if !found.is_empty() {
+                trace!(file = %relative.display(), modules = found.len(), "splitting modules");
+            }
+            for (id, module) in found {
+                let mut path = Path::new("modules").join(relative).join(sanitize_path(&id));
+                if path.extension().is_none_or(|e| e != "js") {
+                    path.as_mut_os_string().push(".js");
+                }
+                files.insert(path, module.to_string());
+            }
+        }
+    }
+    files
+}
+
+/// finds the source map of a file, either from its sourceMappingURL comment or from a `.map` file next to it
+fn find_source_map(path: &Path, contents: &str) -> Result<Option<SourceMap>> {
+    let url = contents
+        .rfind("sourceMappingURL=")
+        .map(|i| {
+            contents[i + "sourceMappingURL=".len()..]
+                .split(|c: char| c.is_whitespace() || c == '*')
+                .next()
+                .unwrap_or_default()
+        })
+        .filter(|url| !url.is_empty());
+
+    let json = match url {
+        Some(url) if url.starts_with("data:") => {
+            let (header, data) = url.split_once(',').context("invalid data url")?;
+            if header.ends_with(";base64") {
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .context("invalid base64 in data url")?
+            } else {
+                data.as_bytes().to_vec()
+            }
+        }
+        Some(url) if !url.contains("://") => {
+            let map_path = path.with_file_name(url.split(['?', '#']).next().unwrap_or(url));
+            match std::fs::read(&map_path) {
+                Ok(json) => json,
+                Err(_) => return Ok(None),
+            }
+        }
+        _ => {
+            let mut map_path = path.as_os_str().to_owned();
+            map_path.push(".map");
+            match std::fs::read(map_path) {
+                Ok(json) => json,
+                Err(_) => return Ok(None),
+            }
+        }
+    };
+    Ok(Some(
+        serde_json::from_slice(&json).context("invalid source map")?,
+    ))
+}
+
+fn add_sources(map: &SourceMap, files: &mut BTreeMap<PathBuf, String>) {
+    for (source, content) in map.sources.iter().zip(&map.sources_content) {
+        let (Some(source), Some(content)) = (source, content) else {
+            continue;
+        };
+        let source = match map.source_root {
+            Some(ref root) if !root.is_empty() => {
+                format!("{}/{source}", root.trim_end_matches('/'))
+            }
+            _ => source.clone(),
+        };
+        let path = Path::new("sources").join(sanitize_path(&source));
+        // bundles often share sources (like the webpack runtime), so the first bundle that has a source wins
+        files.entry(path).or_insert_with(|| content.clone());
+    }
+}
+
+/// turns a source or module id like `webpack://app/./src/index.js?abc` into a safe relative path like `app/src/index.js`
+fn sanitize_path(source: &str) -> PathBuf {
+    let source = source.split_once("://").map_or(source, |(_, rest)| rest);
+    let source = source.split(['?', '#']).next().unwrap_or_default();
+    let mut path = PathBuf::new();
+    for component in Path::new(source).components() {
+        if let Component::Normal(part) = component {
+            let part = part.to_string_lossy().replace(
+                |c: char| c.is_control() || matches!(c, ':' | '*' | '"' | '<' | '>' | '|'),
+                "_",
+            );
+            path.push(part);
+        }
+    }
+    if path.as_os_str().is_empty() {
+        path.push("_");
+    }
+    path
+}
+
+/// finds webpack style module registries (`{123: function (e, t, n) {...}, ...}` or `[function (e, t) {...}, ...]`)
+/// and returns the source of each module by its id
+fn split_modules(source: &str) -> Vec<(String, &str)> {
+    let tokens = tokenize_js(source)
+        .into_iter()
+        .filter(|t| !t.is_comment())
+        .collect::<Vec<_>>();
+    let matching = match_brackets(&tokens);
+    let parser = Parser {
+        source,
+        tokens: &tokens,
+        matching: &matching,
+    };
+
+    let mut modules = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        let registry = if tokens[i].is("{") {
+            parser.object_registry(i)
+        } else if tokens[i].is("[") && i > 0 && (tokens[i - 1].is("(") || tokens[i - 1].is(",")) {
+            parser.array_registry(i)
+        } else {
+            None
+        };
+        match registry {
+            Some(found) if found.len() >= MIN_MODULES => {
+                i = matching[i];
+                modules.extend(found);
+            }
+            _ => i += 1,
+        }
+    }
+    modules
+}
+
+/// returns the index of the matching bracket for each opening bracket, or usize::MAX
+fn match_brackets(tokens: &[Token]) -> Vec<usize> {
+    let mut matching = vec![usize::MAX; tokens.len()];
+    let mut stack: Vec<usize> = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Punct {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => stack.push(i),
+            ")" | "]" | "}" => {
+                let open = match token.text {
+                    ")" => "(",
+                    "]" => "[",
+                    _ => "{",
+                };
+                // unbalanced brackets are ignored
+                if stack.last().is_some_and(|&j| tokens[j].is(open)) {
+                    matching[stack.pop().unwrap()] = i;
+                }
+            }
+            _ => {}
+        }
+    }
+    matching
+}
+
+struct Parser<'s, 't> {
+    source: &'s str,
+    tokens: &'t [Token<'s>],
+    matching: &'t [usize],
+}
+
+impl<'s> Parser<'s, '_> {
+    fn token(&self, i: usize) -> Option<&Token<'s>> {
+        self.tokens.get(i)
+    }
+
+    fn close(&self, open: usize) -> Option<usize> {
+        Some(self.matching[open]).filter(|&i| i != usize::MAX)
+    }
+
+    /// `{id: function, ...}`, where every id is a number or a string
+    fn object_registry(&self, open: usize) -> Option<Vec<(String, &'s str)>> {
+        let end = self.close(open)?;
+        let mut modules = vec![];
+        let mut i = open + 1;
+        while i < end {
+            let key = self.token(i)?;
+            let id = match key.kind {
+                TokenKind::Number => key.text.to_string(),
+                TokenKind::String => {
+                    // the tokenizer doesn't require strings to be terminated
+                    key.text
+                        .strip_prefix(['"', '\''])?
+                        .strip_suffix(['"', '\''])?
+                        .to_string()
+                }
+                _ => return None,
+            };
+            // string ids are paths (`./src/index.js`), anything else is probably an object of methods
+            if key.kind == TokenKind::String
+                && !id.contains('/')
+                && !id.bytes().all(|b| b.is_ascii_digit())
+            {
+                return None;
+            }
+            if !self.token(i + 1)?.is(":") {
+                return None;
+            }
+            let function_end = self.function(i + 2)?;
+            modules.push((id, self.slice(i + 2, function_end)));
+            i = function_end + 1;
+            if i < end && !self.token(i)?.is(",") {
+                return None;
+            }
+            i += 1;
+        }
+        Some(modules)
+    }
+
+    /// `[function, , function, ...]`, where the index is the id
+    fn array_registry(&self, open: usize) -> Option<Vec<(String, &'s str)>> {
+        let end = self.close(open)?;
+        let mut modules = vec![];
+        let mut id = 0;
+        let mut i = open + 1;
+        while i < end {
+            if self.token(i)?.is(",") {
+                id += 1;
+                i += 1;
+                continue;
+            }
+            let function_end = self.function(i)?;
+            modules.push((id.to_string(), self.slice(i, function_end)));
+            i = function_end + 1;
+            if i < end && !self.token(i)?.is(",") {
+                return None;
+            }
+            id += 1;
+            i += 1;
+        }
+        Some(modules)
+    }
+
+    /// parses `function (a, b) {...}`, `(a, b) => {...}` or `a => {...}` at i, returning the index of the closing brace
+    fn function(&self, mut i: usize) -> Option<usize> {
+        if self.token(i)?.is("async") {
+            i += 1;
+        }
+        let token = self.token(i)?;
+        if token.is("function") {
+            i += 1;
+            if self.token(i)?.kind == TokenKind::Word {
+                i += 1;
+            }
+            let params_end = self.params(i)?;
+            return self.body(params_end + 1);
+        }
+        let params_end = if token.is("(") {
+            self.params(i)?
+        } else if token.kind == TokenKind::Word && !token.is_keyword() {
+            i
+        } else {
+            return None;
+        };
+        if !self.token(params_end + 1)?.is("=>") {
+            return None;
+        }
+        self.body(params_end + 2)
+    }
+
+    /// checks that the parameter list at open has few enough parameters, returning the index of the closing parenthesis
+    fn params(&self, open: usize) -> Option<usize> {
+        if !self.token(open)?.is("(") {
+            return None;
+        }
+        let close = self.close(open)?;
+        let params = self.tokens[open + 1..close]
+            .iter()
+            .filter(|t| t.kind == TokenKind::Word)
+            .count();
+        (params <= MAX_MODULE_PARAMS).then_some(close)
+    }
+
+    fn body(&self, open: usize) -> Option<usize> {
+        if !self.token(open)?.is("{") {
+            return None;
+        }
+        self.close(open)
+    }
+
+    fn slice(&self, start: usize, end: usize) -> &'s str {
+        let end = &self.tokens[end];
+        &self.source[self.tokens[start].start..end.start + end.text.len()]
+    }
+}
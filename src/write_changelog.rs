@@ -0,0 +1,211 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tracing::{debug, error, info};
+
+use crate::extensions::Extension;
+use crate::get_update::Update;
+use crate::notify::CheckedExtension;
+use crate::record_history::rfc3339;
+
+const CHANGELOG_DIR: &str = "./changelog";
+
+/// the section of the changelog for an update, starting with its heading
+fn changelog_section(extension: &Extension, update: &Update, date: &str) -> String {
+    let mut section = format!(
+        "## {} -> {} ({date})\n\n{} update, {} severity\n",
+        update.prev_version,
+        update.cur_version,
+        update.version_change(),
+        update.severity()
+    );
+    let diffs = update.diff_files(extension);
+    if !diffs.is_empty() {
+        section.push('\n');
+        for (filename, _) in diffs {
+            section.push_str(&format!("- diff: `./diff/{filename}`\n"));
+        }
+    }
+
+    section.push_str("\n### Manifest Changes\n\n");
+    match update.manifest_changes {
+        Some(ref changes) if !changes.is_empty() => {
+            for permission in &changes.added_permissions {
+                section.push_str(&format!("- added `{permission}`\n"));
+            }
+            for permission in &changes.removed_permissions {
+                section.push_str(&format!("- removed `{permission}`\n"));
+            }
+            for (key, prev, cur) in &changes.changed_keys {
+                section.push_str(&match (prev, cur) {
+                    (Some(prev), Some(cur)) => {
+                        format!("- changed `{key}` from `{prev}` to `{cur}`\n")
+                    }
+                    (None, Some(cur)) => format!("- added `{key}`: `{cur}`\n"),
+                    (Some(_), None) => format!("- removed `{key}`\n"),
+                    (None, None) => format!("- changed `{key}`\n"),
+                });
+            }
+        }
+        Some(_) => section.push_str("no changes\n"),
+        None => section.push_str("the manifests couldn't be compared\n"),
+    }
+
+    if let Some(ref changes) = update.endpoint_changes {
+        if !changes.is_empty() {
+            section.push_str("\n### Endpoint Changes\n\n");
+            for (kind, endpoint) in changes.added.labeled() {
+                section.push_str(&format!("- added {kind} `{endpoint}`\n"));
+            }
+            for (kind, endpoint) in changes.removed.labeled() {
+                section.push_str(&format!("- removed {kind} `{endpoint}`\n"));
+            }
+        }
+    }
+
+    section.push_str("\n### File Changes\n\n");
+    if update.file_changes.is_empty() {
+        section.push_str("no changes\n");
+    }
+    for change in &update.file_changes {
+        section.push_str(&format!(
+            "- {} `{}`{}\n",
+            change.kind,
+            change.path,
+            if change.ignored { " (ignored)" } else { "" }
+        ));
+    }
+
+    if !update.secrets.is_empty() {
+        section.push_str("\n### Possible Secrets\n\n");
+        for secret in &update.secrets {
+            section.push_str(&format!(
+                "- {} `{}` in `{}:{}`\n",
+                secret.kind,
+                secret.displayed_value(),
+                secret.file,
+                secret.line
+            ));
+        }
+    }
+    section
+}
+
+/// adds a section for the update to the top of {dir}/{name}.md, creating it if it doesn't exist
+async fn write_changelog(
+    dir: &str,
+    extension: &Extension,
+    update: &Update,
+    date: &str,
+) -> Result<()> {
+    let path = format!("{dir}/{}.md", extension.name);
+    let changelog = match tokio::fs::read_to_string(&path).await {
+        Ok(changelog) => changelog,
+        Err(error) => {
+            debug!(%error, "failed to read changelog, creating it");
+            format!(
+                "# {} changelog\n\nevery version of {} (`{}`) found by extension-version-watcher, newest first\n",
+                extension.display_name, extension.display_name, extension.id
+            )
+        }
+    };
+    // the new section goes before the previous newest section
+    let (header, sections) = match changelog.find("\n## ") {
+        Some(i) => changelog.split_at(i + 1),
+        None => (changelog.as_str(), ""),
+    };
+    let section = changelog_section(extension, update, date);
+    let changelog = if sections.is_empty() {
+        format!("{}\n\n{section}", header.trim_end())
+    } else {
+        format!("{}\n\n{section}\n{sections}", header.trim_end())
+    };
+    tokio::fs::write(&path, changelog)
+        .await
+        .with_context(|| format!("couldn't write {path}"))
+}
+
+/// adds the updates of this run to the changelogs in ./changelog
+#[tracing::instrument(skip_all)]
+pub async fn write_changelogs(checked_extensions: &[CheckedExtension]) -> Result<()> {
+    tokio::fs::create_dir_all(CHANGELOG_DIR)
+        .await
+        .context("couldn't create changelog dir")?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    // like 2023-09-14
+    let date = &rfc3339(now)[..10];
+    for (extension, update) in checked_extensions {
+        if let Ok(Some(update)) = update {
+            // one changelog failing shouldn't stop the others from being written
+            match write_changelog(CHANGELOG_DIR, extension, update, date).await {
+                Ok(()) => info!(extension = extension.name, "updated changelog"),
+                Err(error) => error!(extension = extension.name, "{error:#}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::updated_extension;
+
+    #[tokio::test]
+    async fn newest_section_first() {
+        let dir = std::env::temp_dir().join(format!(
+            "extension-version-watcher-changelog-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_str().unwrap();
+
+        let (extension, update) = updated_extension("foo", Some("-old\n+new\n"));
+        let mut update = update.unwrap().unwrap();
+        write_changelog(dir, &extension, &update, "2023-09-14")
+            .await
+            .unwrap();
+        update.prev_version = "1.1".into();
+        update.cur_version = "1.2".into();
+        write_changelog(dir, &extension, &update, "2023-10-01")
+            .await
+            .unwrap();
+
+        let changelog = std::fs::read_to_string(format!("{dir}/foo.md")).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(changelog.starts_with(&format!(
+            "# FOO changelog\n\nevery version of FOO (`{}`) found by extension-version-watcher, newest first\n\n\
+             ## 1.1 -> 1.2 (2023-10-01)\n\nminor update",
+            extension.id
+        )));
+        assert_eq!(changelog.matches("# FOO changelog").count(), 1);
+        let newer = changelog.find("## 1.1 -> 1.2 (2023-10-01)").unwrap();
+        let older = changelog.find("## 1.0 -> 1.1 (2023-09-14)").unwrap();
+        assert!(newer < older);
+        assert!(changelog.contains("- diff: `./diff/foo-1.0-1.1.diff`"));
+        // sections are separated by one blank line
+        assert!(changelog.contains("no changes\n\n## 1.0 -> 1.1"));
+        assert!(changelog.ends_with("no changes\n"));
+    }
+}
@@ -0,0 +1,369 @@
+/*
+extension-version-watcher: rust program to check for updates in chrome extensions
+Copyright (C) 2023  staticallyamazing
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::html_report::escape;
+use crate::record_history::{rfc3339, HistoryEntry};
+
+const FEED_DIR: &str = "./feed";
+
+fn default_title() -> String {
+    "Extension updates".into()
+}
+
+const fn default_max_entries() -> usize {
+    50
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedConfig {
+    #[serde(default = "default_title")]
+    title: String,
+    /// the number of updates in each feed, newest first
+    #[serde(default = "default_max_entries")]
+    max_entries: usize,
+    /// if a json feed should be written next to the atom feed
+    #[serde(default)]
+    json_feed: bool,
+    /// if a feed should also be written for every extension
+    #[serde(default)]
+    per_extension: bool,
+    /// a url that serves the ./feed directory, for the feeds' links to themselves
+    feed_base_url: Option<String>,
+    /// a url that serves the ./diff directory. entries link to their diffs there, or mention their paths if this
+    /// isn't set
+    diff_base_url: Option<String>,
+}
+
+/// a diff's url, or its path if there is no diff_base_url
+fn diff_link(config: &FeedConfig, filename: &str) -> String {
+    match config.diff_base_url {
+        Some(ref base_url) => format!("{}/{filename}", base_url.trim_end_matches('/')),
+        None => format!("./diff/{filename}"),
+    }
+}
+
+fn entry_id(entry: &HistoryEntry) -> String {
+    format!(
+        "urn:extension-version-watcher:update:{}:{}:{}:{}",
+        entry.name, entry.prev_version, entry.cur_version, entry.checked_at
+    )
+}
+
+fn entry_title(entry: &HistoryEntry) -> String {
+    format!(
+        "{}: {} -> {}",
+        entry.display_name, entry.prev_version, entry.cur_version
+    )
+}
+
+/// the changes of an update, one per line, without the diffs
+fn change_summary(entry: &HistoryEntry) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "{} update, {} severity",
+            entry.version_change, entry.severity
+        ),
+        format!(
+            "files: {} added, {} removed, {} modified",
+            entry.files_added, entry.files_removed, entry.files_modified
+        ),
+    ];
+    if !entry.added_permissions.is_empty() {
+        lines.push(format!(
+            "added permissions: {}",
+            entry.added_permissions.join(", ")
+        ));
+    }
+    if !entry.removed_permissions.is_empty() {
+        lines.push(format!(
+            "removed permissions: {}",
+            entry.removed_permissions.join(", ")
+        ));
+    }
+    if !entry.changed_keys.is_empty() {
+        lines.push(format!(
+            "changed manifest keys: {}",
+            entry.changed_keys.join(", ")
+        ));
+    }
+    if entry.added_endpoints > 0 || entry.removed_endpoints > 0 {
+        lines.push(format!(
+            "endpoints: {} added, {} removed",
+            entry.added_endpoints, entry.removed_endpoints
+        ));
+    }
+    if !entry.secrets.is_empty() {
+        let mut kinds = entry.secrets.clone();
+        kinds.sort();
+        kinds.dedup();
+        lines.push(format!(
+            "{} possible secrets: {}",
+            entry.secrets.len(),
+            kinds.join(", ")
+        ));
+    }
+    lines
+}
+
+fn content_text(config: &FeedConfig, entry: &HistoryEntry) -> String {
+    let mut lines = change_summary(entry);
+    for diff in &entry.diffs {
+        lines.push(format!("diff: {}", diff_link(config, diff)));
+    }
+    lines.join("\n")
+}
+
+fn content_html(config: &FeedConfig, entry: &HistoryEntry) -> String {
+    let mut html = String::from("<ul>");
+    for line in change_summary(entry) {
+        html.push_str(&format!("<li>{}</li>", escape(&line)));
+    }
+    for diff in &entry.diffs {
+        let link = escape(&diff_link(config, diff));
+        if config.diff_base_url.is_some() {
+            html.push_str(&format!("<li>diff: <a href=\"{link}\">{link}</a></li>"));
+        } else {
+            html.push_str(&format!("<li>diff: <code>{link}</code></li>"));
+        }
+    }
+    html.push_str("</ul>");
+    html
+}
+
+fn atom_feed(
+    config: &FeedConfig,
+    id: &str,
+    title: &str,
+    filename: &str,
+    entries: &[&HistoryEntry],
+) -> String {
+    let updated = rfc3339(entries.first().map_or(0, |entry| entry.checked_at));
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>{}</id>
+  <title>{}</title>
+  <updated>{updated}</updated>
+  <author><name>extension-version-watcher</name></author>
+  <generator uri="{}" version="{}">extension-version-watcher</generator>
+"#,
+        escape(id),
+        escape(title),
+        env!("CARGO_PKG_REPOSITORY"),
+        env!("CARGO_PKG_VERSION"),
+    );
+    if let Some(ref base_url) = config.feed_base_url {
+        xml.push_str(&format!(
+            "  <link rel=\"self\" href=\"{}/{}\"/>\n",
+            escape(base_url.trim_end_matches('/')),
+            escape(filename)
+        ));
+    }
+    for entry in entries {
+        xml.push_str(&format!(
+            "  <entry>\n    <id>{}</id>\n    <title>{}</title>\n    <updated>{}</updated>\n",
+            escape(&entry_id(entry)),
+            escape(&entry_title(entry)),
+            rfc3339(entry.checked_at),
+        ));
+        if let (Some(diff), Some(_)) = (entry.diffs.first(), &config.diff_base_url) {
+            xml.push_str(&format!(
+                "    <link rel=\"alternate\" href=\"{}\"/>\n",
+                escape(&diff_link(config, diff))
+            ));
+        }
+        xml.push_str(&format!(
+            "    <category term=\"{}\"/>\n    <content type=\"html\">{}</content>\n  </entry>\n",
+            escape(&entry.severity),
+            escape(&content_html(config, entry)),
+        ));
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// see https://www.jsonfeed.org/version/1.1/
+fn json_feed(
+    config: &FeedConfig,
+    title: &str,
+    filename: &str,
+    entries: &[&HistoryEntry],
+) -> String {
+    let items = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "id": entry_id(entry),
+                "title": entry_title(entry),
+                "url": entry
+                    .diffs
+                    .first()
+                    .filter(|_| config.diff_base_url.is_some())
+                    .map(|diff| diff_link(config, diff)),
+                "content_html": content_html(config, entry),
+                "content_text": content_text(config, entry),
+                "date_published": rfc3339(entry.checked_at),
+                "tags": [entry.severity, entry.version_change],
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": title,
+        "authors": [{ "name": "extension-version-watcher", "url": env!("CARGO_PKG_REPOSITORY") }],
+        "items": items,
+    });
+    if let Some(ref base_url) = config.feed_base_url {
+        feed["feed_url"] = format!("{}/{filename}", base_url.trim_end_matches('/')).into();
+    }
+    serde_json::to_string_pretty(&feed).unwrap()
+}
+
+/// writes the atom (and json) feed of the updates to ./feed, relative to the directory
+async fn write_feed(
+    config: &FeedConfig,
+    path: &str,
+    id: &str,
+    title: &str,
+    entries: &[&HistoryEntry],
+) -> Result<()> {
+    let entries = &entries[..entries.len().min(config.max_entries)];
+    let filename = format!("{path}.atom");
+    tokio::fs::write(
+        format!("{FEED_DIR}/{filename}"),
+        atom_feed(config, id, title, &filename, entries),
+    )
+    .await
+    .with_context(|| format!("failed to write {filename}"))?;
+    if config.json_feed {
+        let filename = format!("{path}.json");
+        tokio::fs::write(
+            format!("{FEED_DIR}/{filename}"),
+            json_feed(config, title, &filename, entries),
+        )
+        .await
+        .with_context(|| format!("failed to write {filename}"))?;
+    }
+    Ok(())
+}
+
+/// regenerates the feeds in ./feed from the history
+#[tracing::instrument(skip_all)]
+pub async fn write_feeds(config: &FeedConfig, history: &[HistoryEntry]) -> Result<()> {
+    tokio::fs::create_dir_all(FEED_DIR)
+        .await
+        .context("couldn't create feed dir")?;
+    // newest first
+    let entries = history.iter().rev().collect::<Vec<_>>();
+    write_feed(
+        config,
+        "updates",
+        "urn:extension-version-watcher:feed",
+        &config.title,
+        &entries,
+    )
+    .await?;
+
+    if config.per_extension {
+        tokio::fs::create_dir_all(format!("{FEED_DIR}/extensions"))
+            .await
+            .context("couldn't create feed/extensions dir")?;
+        let mut by_extension = BTreeMap::<&str, Vec<&HistoryEntry>>::new();
+        for entry in &entries {
+            by_extension.entry(&entry.name).or_default().push(entry);
+        }
+        for (name, entries) in by_extension {
+            // one extension's feed failing shouldn't stop the others from being written
+            if let Err(error) = write_feed(
+                config,
+                &format!("extensions/{name}"),
+                &format!("urn:extension-version-watcher:feed:{name}"),
+                &format!("{}: {}", config.title, entries[0].display_name),
+                &entries,
+            )
+            .await
+            {
+                error!(extension = name, "{error:#}");
+            }
+        }
+    }
+    info!(entries = history.len(), "wrote feeds");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_history::history_entry;
+
+    #[test]
+    fn render_feeds() {
+        let config: FeedConfig = toml::from_str(
+            r#"
+            title = "Updates & more"
+            feed_base_url = "https://example.com/feed/"
+            diff_base_url = "https://example.com/diff"
+            "#,
+        )
+        .unwrap();
+        let mut older = history_entry("foo", 1694714580);
+        older.display_name = "<Foo>".into();
+        let newer = history_entry("bar", 1709210096);
+        let entries = [&newer, &older];
+
+        let atom = atom_feed(&config, "urn:feed", &config.title, "updates.atom", &entries);
+        assert!(atom.contains("<title>Updates &amp; more</title>"));
+        assert!(atom.contains("<updated>2024-02-29T12:34:56Z</updated>"));
+        assert!(atom.contains(r#"<link rel="self" href="https://example.com/feed/updates.atom"/>"#));
+        assert!(atom.contains(
+            "<id>urn:extension-version-watcher:update:foo:1.0:1.1:1694714580</id>\n    \
+             <title>&lt;Foo&gt;: 1.0 -&gt; 1.1</title>\n    <updated>2023-09-14T18:03:00Z</updated>"
+        ));
+        assert!(atom.contains(
+            r#"<link rel="alternate" href="https://example.com/diff/bar-1.0-1.1.diff"/>"#
+        ));
+        // the html content is escaped again inside the xml
+        assert!(atom.contains("&lt;li&gt;added permissions: tabs&lt;/li&gt;"));
+        assert!(atom.find("BAR").unwrap() < atom.find("Foo").unwrap());
+        assert!(atom.ends_with("</feed>\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&json_feed(&config, &config.title, "updates.json", &entries))
+                .unwrap();
+        assert_eq!(json["title"], "Updates & more");
+        assert_eq!(json["feed_url"], "https://example.com/feed/updates.json");
+        let items = json["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["title"], "BAR: 1.0 -> 1.1");
+        assert_eq!(items[0]["date_published"], "2024-02-29T12:34:56Z");
+        assert_eq!(items[0]["url"], "https://example.com/diff/bar-1.0-1.1.diff");
+        assert_eq!(items[0]["tags"], json!(["low", "minor"]));
+        assert_eq!(items[1]["title"], "<Foo>: 1.0 -> 1.1");
+        assert!(items[1]["content_text"]
+            .as_str()
+            .unwrap()
+            .contains("diff: https://example.com/diff/foo-1.0-1.1.diff"));
+    }
+}